@@ -0,0 +1,223 @@
+//! # Archive
+//!
+//! The archive module lets images inside ZIP and CBZ archives be viewed without extracting them.
+//! An image inside an archive is tracked by a virtual path, the path of the archive joined with
+//! the name of the entry, such as `comics/issue1.cbz/page01.jpg`.
+//!
+//! Entries are recorded as the images are discovered, so a virtual path is told apart from a file
+//! without touching the disk, and the index of each archive is only read once.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use zip::ZipArchive;
+
+/// Archives and the virtual paths of the images found inside them
+#[derive(Default)]
+struct Entries {
+    archives: HashSet<PathBuf>,
+    images: HashSet<PathBuf>,
+}
+
+lazy_static! {
+    static ref ENTRIES: Mutex<Entries> = Mutex::new(Entries::default());
+    /// Archives that have been read from, kept open with their index of entries
+    static ref OPEN: Mutex<HashMap<PathBuf, ZipArchive<File>>> = Mutex::new(HashMap::new());
+}
+
+/// Whether the path has the extension of a supported archive, `.zip` or `.cbz`
+pub fn is_archive(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => {
+            let low = ext.to_lowercase();
+            low == "zip" || low == "cbz"
+        }
+        None => false,
+    }
+}
+
+/// Splits the virtual path of an archive entry into the archive on disk and the entry name
+/// Returns None if the path is not an image found inside an archive by `image_entries`
+pub fn split_entry(path: &Path) -> Option<(&Path, String)> {
+    let entries = ENTRIES.lock().unwrap();
+    if !entries.images.contains(path) {
+        return None;
+    }
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| entries.archives.contains(*ancestor))?;
+    // Entry names always use forward slashes regardless of platform
+    let name: Vec<String> = path
+        .strip_prefix(archive)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some((archive, name.join("/")))
+}
+
+/// Whether the path is the virtual path of an image inside an archive
+pub fn is_entry(path: &Path) -> bool {
+    split_entry(path).is_some()
+}
+
+/// The file on disk that holds the image, the archive for archive entries
+pub fn containing_file(path: &Path) -> &Path {
+    match split_entry(path) {
+        Some((archive, _)) => archive,
+        None => path,
+    }
+}
+
+/// Virtual paths of every image in the archive, in the order they are stored
+/// Directories, hidden files and entries that would point outside of the archive are skipped
+/// The images are recorded as archive entries
+pub fn image_entries(archive: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut open = OPEN.lock().unwrap();
+    let zip = opened(&mut open, archive)?;
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip.by_index(i).map_err(|e| e.to_string())?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = Path::new(file.name());
+        let safe = name
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        // Skips resource forks such as `__MACOSX/._page01.jpg` along with dotfiles
+        let hidden = name.components().any(|component| {
+            let part = component.as_os_str().to_string_lossy();
+            part.starts_with('.') || part == "__MACOSX"
+        });
+        if safe && !hidden {
+            crate::cli::push_image_path(&mut entries, archive.join(name));
+        }
+    }
+    let mut recorded = ENTRIES.lock().unwrap();
+    recorded.archives.insert(archive.to_path_buf());
+    recorded.images.extend(entries.iter().cloned());
+    Ok(entries)
+}
+
+/// Reads the contents of the archive entry at the virtual path into memory
+pub fn read_entry(path: &Path) -> Result<Vec<u8>, String> {
+    let (archive, name) = match split_entry(path) {
+        Some(split) => split,
+        None => return Err(format!("\"{}\" is not inside an archive", path.display())),
+    };
+    let mut open = OPEN.lock().unwrap();
+    let mut file = opened(&mut open, archive)?
+        .by_name(&name)
+        .map_err(|e| format!("failed to read \"{}\": {}", name, e))?;
+    let mut bytes = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Writes the archive entry at the virtual path to `dest`
+/// Like copying a file, an existing file at `dest` is not overwritten
+pub fn extract_entry(path: &Path, dest: &Path) -> Result<(), String> {
+    let bytes = read_entry(path)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)
+        .map_err(|e| format!("failed to extract to \"{}\": {}", dest.display(), e))?;
+    file.write_all(&bytes).map_err(|e| e.to_string())
+}
+
+/// Dimensions of the image at the path, reading only its header
+/// Images inside archives are read from memory
+pub fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+    if !is_entry(path) {
+        return image::image_dimensions(path).ok();
+    }
+    let bytes = read_entry(path).ok()?;
+    image::io::Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// The archive from the archives already open, opening it and reading its index the first time
+fn opened<'a>(
+    open: &'a mut HashMap<PathBuf, ZipArchive<File>>,
+    archive: &Path,
+) -> Result<&'a mut ZipArchive<File>, String> {
+    if !open.contains_key(archive) {
+        let file = File::open(archive).map_err(|e| format!("{}: {}", archive.display(), e))?;
+        let zip = ZipArchive::new(file).map_err(|e| format!("{}: {}", archive.display(), e))?;
+        open.insert(archive.to_path_buf(), zip);
+    }
+    Ok(open.get_mut(archive).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_entry, image_entries, is_entry, read_entry, split_entry};
+    use crate::test_dir::TestDir;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn write_archive(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_image_entries_skip_other_files_and_unsafe_names() {
+        let dir = TestDir::new("archive-entries");
+        let archive = dir.join("issue1.CBZ");
+        write_archive(
+            &archive,
+            &[
+                ("page02.png", b"2"),
+                ("credits.txt", b""),
+                ("extra/page03.jpg", b"3"),
+                ("__MACOSX/._page02.png", b""),
+                ("../outside.png", b""),
+            ],
+        );
+
+        let entries = image_entries(&archive).unwrap();
+        assert_eq!(
+            entries,
+            vec![archive.join("page02.png"), archive.join("extra/page03.jpg")]
+        );
+        assert!(is_entry(&entries[1]));
+        assert!(!is_entry(&archive));
+    }
+
+    #[test]
+    fn test_entries_are_read_and_extracted_by_virtual_path() {
+        let dir = TestDir::new("archive-read");
+        let archive = dir.join("photos.zip");
+        write_archive(&archive, &[("trip/a.jpg", b"image data")]);
+        let entry = archive.join("trip").join("a.jpg");
+        // Only entries found when the archive was listed are read
+        assert!(split_entry(&entry).is_none());
+        assert_eq!(image_entries(&archive).unwrap(), vec![entry.clone()]);
+
+        let (on_disk, name) = split_entry(&entry).unwrap();
+        assert_eq!((on_disk, name.as_str()), (archive.as_path(), "trip/a.jpg"));
+        assert_eq!(read_entry(&entry).unwrap(), b"image data");
+
+        let dest = dir.join("a.jpg");
+        extract_entry(&entry, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"image data");
+        // Existing files are left alone
+        assert!(extract_entry(&entry, &dest).is_err());
+        assert!(read_entry(&archive.join("missing.jpg")).is_err());
+    }
+}
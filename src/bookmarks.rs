@@ -0,0 +1,166 @@
+//! # Bookmarks
+//!
+//! The bookmarks module keeps images bookmarked under a letter and folders bookmarked under a
+//! name, saved in the config directory so they are kept between runs.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Images bookmarked by letter and folders bookmarked by name
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bookmarks {
+    /// Bookmarked images by their letter
+    #[serde(default)]
+    images: BTreeMap<String, PathBuf>,
+    /// Bookmarked folders by their name, used as `@name` in paths
+    #[serde(default)]
+    folders: BTreeMap<String, PathBuf>,
+    /// File the bookmarks are saved in, None if they can't be saved
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    /// Reads the bookmarks saved in the file, starting without bookmarks if there is no file or
+    /// it can't be read
+    pub fn open(file: Option<PathBuf>) -> Bookmarks {
+        let mut bookmarks = match file.as_deref().map(read) {
+            Some(Ok(bookmarks)) => bookmarks,
+            Some(Err(e)) => {
+                eprintln!("Failed to read bookmarks: {}", e);
+                Bookmarks::default()
+            }
+            None => Bookmarks::default(),
+        };
+        bookmarks.file = file;
+        bookmarks
+    }
+
+    /// The image bookmarked under the letter
+    pub fn image(&self, letter: char) -> Option<&PathBuf> {
+        self.images.get(&letter.to_string())
+    }
+
+    /// Bookmarks the image under the letter, replacing the image bookmarked under it before
+    pub fn set_image(&mut self, letter: char, path: &Path) -> Result<(), String> {
+        self.images.insert(letter.to_string(), path.to_path_buf());
+        self.save()
+    }
+
+    /// The bookmarked folders and their names, in order of name
+    pub fn folders(&self) -> impl Iterator<Item = (&String, &PathBuf)> {
+        self.folders.iter()
+    }
+
+    /// Bookmarks the folder under the name, replacing the folder bookmarked under it before
+    pub fn set_folder(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        if name.is_empty() || name.contains(|c: char| c == '/' || c.is_whitespace()) {
+            return Err(format!("\"{}\" can't be used as a bookmark name", name));
+        }
+        self.folders.insert(name.to_string(), path.to_path_buf());
+        self.save()
+    }
+
+    /// Replaces a leading `@name` in the path with the folder bookmarked under the name, so
+    /// `@holiday/*.jpg` finds the JPEGs in the holiday folder
+    /// Paths without a leading `@` are returned as they are
+    pub fn expand(&self, path: &str) -> Result<String, String> {
+        let rest = match path.strip_prefix('@') {
+            Some(rest) => rest,
+            None => return Ok(path.to_string()),
+        };
+        let (name, rest) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+        let folder = self
+            .folders
+            .get(name)
+            .ok_or_else(|| format!("No folder is bookmarked as \"{}\"", name))?;
+        let expanded = if rest.is_empty() {
+            folder.clone()
+        } else {
+            folder.join(rest)
+        };
+        Ok(expanded.to_string_lossy().into_owned())
+    }
+
+    /// Writes the bookmarks to their file, creating its directory if needed
+    fn save(&self) -> Result<(), String> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Err("failed to find a directory to keep bookmarks in".to_string()),
+        };
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(file, json).map_err(|e| format!("{}: {}", file.display(), e))
+    }
+}
+
+/// Default file bookmarks are saved in, `$XDG_CONFIG_HOME/riv/bookmarks.json`
+/// Falls back to `~/.config/riv/bookmarks.json` if `$XDG_CONFIG_HOME` is not set
+pub fn default_bookmarks_path() -> Option<PathBuf> {
+    let config_dir = crate::xdg_dir("XDG_CONFIG_HOME", ".config")?;
+    Some(config_dir.join("riv").join("bookmarks.json"))
+}
+
+/// Reads the bookmarks in the file, a file that isn't there holds no bookmarks
+fn read(file: &Path) -> Result<Bookmarks, String> {
+    if !file.exists() {
+        return Ok(Bookmarks::default());
+    }
+    let json = fs::read_to_string(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| format!("{}: {}", file.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bookmarks;
+    use crate::test_dir::TestDir;
+    use std::path::Path;
+
+    #[test]
+    fn test_bookmarks_are_kept_between_runs() {
+        let dir = TestDir::new("bookmarks");
+        let file = dir.join("riv").join("bookmarks.json");
+        let mut bookmarks = Bookmarks::open(Some(file.clone()));
+        bookmarks
+            .set_image('a', Path::new("/photos/a.jpg"))
+            .unwrap();
+        bookmarks
+            .set_folder("holiday", Path::new("/photos/2019/holiday"))
+            .unwrap();
+        let reopened = Bookmarks::open(Some(file));
+
+        assert_eq!(reopened, bookmarks);
+        assert_eq!(
+            reopened.image('a'),
+            Some(&Path::new("/photos/a.jpg").to_path_buf())
+        );
+        assert_eq!(reopened.image('b'), None);
+    }
+
+    #[test]
+    fn test_folder_names_are_expanded() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks
+            .folders
+            .insert("keep".to_string(), Path::new("/photos/keep").to_path_buf());
+        assert_eq!(bookmarks.expand("@keep"), Ok("/photos/keep".to_string()));
+        assert_eq!(
+            bookmarks.expand("@keep/*.jpg"),
+            Ok("/photos/keep/*.jpg".to_string())
+        );
+        assert_eq!(bookmarks.expand("~/photos"), Ok("~/photos".to_string()));
+        assert!(bookmarks.expand("@lost").is_err());
+        // Saving needs a file
+        assert!(bookmarks.set_folder("new", Path::new("/new")).is_err());
+        assert!(Bookmarks::default()
+            .set_folder("two words", Path::new("/"))
+            .is_err());
+    }
+}
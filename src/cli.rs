@@ -2,11 +2,18 @@
 //!
 //! The cli module is used for setting up the command line app and parsing the arguments.
 
+use crate::conflict::ConflictPolicy;
+use crate::exclude::Exclusions;
+use crate::journal::default_journal_path;
+use crate::list::ListFormat;
+use crate::session::{self, Session};
 use crate::sort::SortOrder;
+use crate::ui::{ConfirmThresholds, FitMode};
 use clap::{App, Arg};
-use glob::glob;
+use glob::glob_with;
+use std::collections::HashSet;
 use std::env::current_dir;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// Args contains the arguments that have been successfully parsed by the clap cli app
 pub struct Args {
@@ -24,18 +31,54 @@ pub struct Args {
     pub fullscreen: bool,
     /// New base directory defaults to std::env::current_dir
     pub base_dir: PathBuf,
+    /// Shell commands bound to keys, as (key, command) pairs
+    pub bindings: Vec<(String, String)>,
+    /// Path of the remote control socket, None if remote control is disabled
+    pub socket: Option<PathBuf>,
+    /// Print the images instead of displaying them, in the given format
+    pub list: Option<ListFormat>,
+    /// Paths to skip when searching for images
+    pub exclusions: Exclusions,
+    /// How images are fit to the window
+    pub fit: FitMode,
+    /// Keep the fit mode and zoom when moving to another image
+    pub keep_view: bool,
+    /// Back up files before changing them in place
+    pub backup: bool,
+    /// What to do when moving or copying an image to a name that is already taken
+    pub on_conflict: ConflictPolicy,
+    /// Keep the directories of images relative to the base directory when moving or copying them
+    pub keep_structure: bool,
+    /// Numbers of images at which destructive actions ask to be confirmed
+    pub confirm: ConfirmThresholds,
+    /// Path of the journal changes to files are recorded in, None if journaling is disabled
+    pub journal: Option<PathBuf>,
+    /// Print the changes recorded in the journal instead of displaying images
+    pub replay_journal: bool,
+    /// Pretend to change files, only updating the images viewed
+    pub dry_run: bool,
+    /// Directory the paths searched for images are relative to
+    pub search_dir: PathBuf,
+    /// The paths, directories or globs searched for images
+    pub globs: Vec<String>,
+    /// Name of the session saved when riv exits, None if no session was asked for
+    pub session: Option<String>,
+    /// The saved session the images and settings were restored from
+    pub resume: Option<Session>,
 }
 
 /// cli sets up the command line app and parses the arguments, using clap.
 pub fn cli() -> Result<Args, String> {
-    let mut files = Vec::new();
     let matches = App::new("riv")
         .version("0.3.0")
         .about("The command line image viewer")
         .arg(
             Arg::with_name("paths")
                 .default_value("*")
-                .help("The directory or files to search for image files. A glob can be used here."),
+                .multiple(true)
+                .help(
+                    "The directories or files to search for image files. Globs can be used here.",
+                ),
         )
         .arg(
             Arg::with_name("dest-folder")
@@ -80,31 +123,183 @@ pub fn cli() -> Result<Args, String> {
                 .short("F")
                 .help("Start app in fullscreen mode"),
         )
+        .arg(
+            Arg::with_name("fit")
+                .long("fit")
+                .value_name("MODE")
+                .takes_value(true)
+                .case_insensitive(true)
+                .possible_values(&FitMode::NAMES)
+                .help("How images are fit to the window"),
+        )
+        .arg(
+            Arg::with_name("keep-view")
+                .long("keep-view")
+                .takes_value(false)
+                .help("Keep the fit mode and zoom when moving to another image"),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .long("backup")
+                .takes_value(false)
+                .help("Keep a copy of images with .bak added to their name before changing them"),
+        )
+        .arg(
+            Arg::with_name("on-conflict")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .takes_value(true)
+                .help(
+                    "What to do when an image is moved or copied to a name that is taken: skip, \
+                     overwrite, suffix, newer or prompt",
+                ),
+        )
+        .arg(
+            Arg::with_name("keep-structure")
+                .long("keep-structure")
+                .takes_value(false)
+                .help(
+                    "Recreate the directories of moved and copied images, relative to the \
+                     searched directory, in the destination folder",
+                ),
+        )
+        .arg(
+            Arg::with_name("confirm")
+                .long("confirm")
+                .value_name("ACTION=COUNT")
+                .help(
+                    "Ask before deleting, trashing or moving at least COUNT images, such as \
+                     trash=10, 0 never asks. Deleting always asks unless set to 0",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("bind")
+                .long("bind")
+                .value_name("KEY=COMMAND")
+                .help(
+                    "Bind a key to a shell command, %f is the current image, %F the marked \
+                     images and %d the destination folder",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("ipc")
+                .long("ipc")
+                .takes_value(false)
+                .help("Listen for remote control commands on $XDG_RUNTIME_DIR/riv.sock"),
+        )
+        .arg(
+            Arg::with_name("socket")
+                .long("socket")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Listen for remote control commands on the given socket path"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .help("Skip images matching the glob, such as \"**/.thumbnails/**\" or \"*_thumb.jpg\"")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .takes_value(false)
+                .help("Search hidden files and directories for images"),
+        )
+        .arg(
+            Arg::with_name("list")
+                .long("list")
+                .takes_value(false)
+                .help("Print the images that would be displayed, in order, then exit"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .takes_value(false)
+                .requires("list")
+                .help("Print the list as JSON, with the size, date and dimensions of each image"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .takes_value(false)
+                .help(
+                    "Pretend to move, copy, link, trash and delete images without changing any \
+                     files, printing what would have been done on exit",
+                ),
+        )
+        .arg(
+            Arg::with_name("session")
+                .long("session")
+                .value_name("NAME")
+                .takes_value(true)
+                .help(
+                    "Restore the images, sorting, marks and current image saved under the name, \
+                     and save them again on exit",
+                ),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .long("journal")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("Record changes made to files in the given journal instead of $XDG_STATE_HOME/riv/journal.jsonl"),
+        )
+        .arg(
+            Arg::with_name("no-journal")
+                .long("no-journal")
+                .takes_value(false)
+                .conflicts_with("journal")
+                .help("Don't record changes made to files in a journal"),
+        )
+        .arg(
+            Arg::with_name("replay-journal")
+                .long("replay-journal")
+                .takes_value(false)
+                .help("Print the changes recorded in the journal, session by session, then exit"),
+        )
         .get_matches();
 
-    let path_glob = match matches.value_of("paths") {
-        Some(v) => v,
+    // A saved session restores the paths and settings it records, unless given on the command
+    // line
+    let session = matches.value_of("session").map(str::to_string);
+    let resume = match &session {
+        Some(name) if session::exists(name) => Some(Session::load(name)?),
+        _ => None,
+    };
+
+    let mut path_globs: Vec<String> = match matches.values_of("paths") {
+        Some(v) => v.map(str::to_string).collect(),
         None => panic!("No value for paths!"),
     };
     // find current directory so glob provided can be relative
-    let mut base_dir = match current_dir() {
+    let mut current_dir = match current_dir() {
         Ok(c) => c,
         Err(_) => PathBuf::new(),
     };
-    let path_glob = crate::path_to_glob(&base_dir, path_glob)?;
-    // find new base directory
-    if let Ok(new_base_dir) = crate::new_base_dir(&path_glob) {
-        base_dir = new_base_dir;
-    }
-    let glob_matches = glob(&path_glob.to_string_lossy()).map_err(|e| e.to_string())?;
-    for path in glob_matches {
-        match path {
-            Ok(p) => push_image_path(&mut files, p),
-            Err(e) => eprintln!("Path not processable {}", e),
+    if let Some(resume) = &resume {
+        if matches.occurrences_of("paths") == 0 {
+            path_globs = resume.globs.clone();
+            current_dir = resume.search_dir.clone();
         }
     }
+    let exclude_patterns: Vec<&str> = match matches.values_of("exclude") {
+        Some(v) => v.collect(),
+        None => Vec::new(),
+    };
+    let exclusions = Exclusions::new(&exclude_patterns, matches.is_present("hidden"))?;
+    let (files, base_dir) = discover_images(&current_dir, &path_globs, &exclusions)?;
 
-    let sort_order = match value_t!(matches, "sort-order", SortOrder) {
+    let mut sort_order = match value_t!(matches, "sort-order", SortOrder) {
         Ok(order) => order,
         Err(e) => {
             eprintln!("{}", e);
@@ -112,15 +307,81 @@ pub fn cli() -> Result<Args, String> {
         }
     };
 
-    let dest_folder = match matches.value_of("dest-folder") {
+    let mut dest_folder = match matches.value_of("dest-folder") {
         Some(f) => PathBuf::from(f),
         None => return Err("failed to determine destination folder".to_string()),
     };
 
-    let reverse = matches.is_present("reverse");
+    let mut reverse = matches.is_present("reverse");
 
-    let max_length = value_t!(matches, "max-number-images", usize).unwrap_or(0);
+    let mut max_length = value_t!(matches, "max-number-images", usize).unwrap_or(0);
+    if let Some(resume) = &resume {
+        if matches.occurrences_of("sort-order") == 0 {
+            sort_order = resume.sort_order.parse()?;
+        }
+        if matches.occurrences_of("dest-folder") == 0 {
+            dest_folder = resume.dest_folder.clone();
+        }
+        if matches.occurrences_of("reverse") == 0 {
+            reverse = resume.reverse;
+        }
+        if matches.occurrences_of("max-number-images") == 0 {
+            max_length = resume.max;
+        }
+    }
     let fullscreen = matches.is_present("fullscreen");
+    let fit = match matches.value_of("fit") {
+        Some(mode) => mode.parse()?,
+        None => FitMode::Fit,
+    };
+    let keep_view = matches.is_present("keep-view");
+    let backup = matches.is_present("backup");
+    let keep_structure = matches.is_present("keep-structure");
+    let on_conflict = match matches.value_of("on-conflict") {
+        Some(policy) => policy.parse()?,
+        None => ConflictPolicy::Skip,
+    };
+
+    let mut confirm = ConfirmThresholds::default();
+    if let Some(values) = matches.values_of("confirm") {
+        for value in values {
+            let mut split = value.splitn(2, '=');
+            let action = split.next().unwrap_or("");
+            match split.next() {
+                Some(count) => confirm.set(action, count)?,
+                None => return Err(format!("Expected ACTION=COUNT, found \"{}\"", value)),
+            }
+        }
+    }
+
+    let mut bindings = Vec::new();
+    if let Some(values) = matches.values_of("bind") {
+        for value in values {
+            bindings.push(parse_binding(value)?);
+        }
+    }
+
+    let socket = match matches.value_of("socket") {
+        Some(path) => Some(PathBuf::from(path)),
+        None if matches.is_present("ipc") => Some(default_socket_path()),
+        None => None,
+    };
+
+    let journal = match matches.value_of("journal") {
+        Some(path) => Some(PathBuf::from(path)),
+        None if matches.is_present("no-journal") => None,
+        None => default_journal_path(),
+    };
+    let replay_journal = matches.is_present("replay-journal");
+    let dry_run = matches.is_present("dry-run");
+
+    let list = if matches.is_present("json") {
+        Some(ListFormat::Json)
+    } else if matches.is_present("list") {
+        Some(ListFormat::Plain)
+    } else {
+        None
+    };
 
     Ok(Args {
         files,
@@ -130,16 +391,140 @@ pub fn cli() -> Result<Args, String> {
         max_length,
         fullscreen,
         base_dir,
+        bindings,
+        socket,
+        list,
+        exclusions,
+        fit,
+        keep_view,
+        backup,
+        on_conflict,
+        keep_structure,
+        confirm,
+        journal,
+        replay_journal,
+        dry_run,
+        search_dir: current_dir,
+        globs: path_globs,
+        session,
+        resume,
     })
 }
 
+/// Finds the image files matching the paths, globs or directories supplied by the user,
+/// relative paths start at `current_dir`
+/// Images matched by more than one path are only included once, and excluded images are skipped
+/// Returns the images found and the base directory, the common ancestor of all of the paths
+pub fn discover_images<S: AsRef<str>>(
+    current_dir: &PathBuf,
+    paths: &[S],
+    exclusions: &Exclusions,
+) -> Result<(Vec<PathBuf>, PathBuf), String> {
+    let mut files = Vec::new();
+    let mut base_dirs = Vec::new();
+    for path in paths {
+        let path_glob = crate::path_to_glob(current_dir, path.as_ref())?;
+        // find new base directory
+        let path_base_dir = match crate::new_base_dir(&path_glob) {
+            Ok(new_base_dir) => new_base_dir,
+            Err(_) => current_dir.clone(),
+        };
+        let glob_matches = glob_with(&path_glob.to_string_lossy(), exclusions.glob_options())
+            .map_err(|e| e.to_string())?;
+        for path in glob_matches {
+            match path {
+                Ok(p) => {
+                    if exclusions.is_excluded(&p, &path_base_dir) {
+                        continue;
+                    }
+                    if crate::archive::is_archive(&p) && p.is_file() {
+                        // The images inside archives are viewed in place of the archive
+                        match crate::archive::image_entries(&p) {
+                            Ok(entries) => files.extend(
+                                entries
+                                    .into_iter()
+                                    .filter(|e| !exclusions.is_excluded(e, &path_base_dir)),
+                            ),
+                            Err(e) => eprintln!("Archive not readable {}", e),
+                        }
+                    } else {
+                        push_image_path(&mut files, p)
+                    }
+                }
+                Err(e) => eprintln!("Path not processable {}", e),
+            }
+        }
+        base_dirs.push(path_base_dir);
+    }
+    // Globs are normalized so the same image always has the same path
+    let mut seen = HashSet::new();
+    files.retain(|path| seen.insert(path.clone()));
+
+    let base_dir = crate::common_ancestor(&base_dirs).unwrap_or_else(|| current_dir.clone());
+    Ok((files, base_dir))
+}
+
+#[cfg(unix)]
+fn default_socket_path() -> PathBuf {
+    crate::ipc::default_socket_path()
+}
+
+#[cfg(not(unix))]
+fn default_socket_path() -> PathBuf {
+    PathBuf::from("riv.sock")
+}
+
+/// Splits a `KEY=COMMAND` binding into its key and command
+pub(crate) fn parse_binding(binding: &str) -> Result<(String, String), String> {
+    let split_index = match binding.find('=') {
+        Some(i) => i,
+        None => {
+            return Err(format!(
+                "Binding \"{}\" must be of the form KEY=COMMAND",
+                binding
+            ))
+        }
+    };
+    let key = &binding[..split_index];
+    let command = binding[split_index + 1..].trim();
+    if key.chars().count() != 1 {
+        return Err(format!(
+            "Binding key \"{}\" must be a single character",
+            key
+        ));
+    }
+    check_binding_key(key)?;
+    if command.is_empty() {
+        return Err(format!("Binding for key \"{}\" has no command", key));
+    }
+    Ok((key.to_string(), command.to_string()))
+}
+
+/// Returns an error if the key can't be bound, as bindings are looked up before the built-in keys
+/// and `:`, `q` and the digits are needed to get to command mode, quit and type counts
+pub(crate) fn check_binding_key(key: &str) -> Result<(), String> {
+    if key == ":" || key == "q" || key.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!(
+            "Key \"{}\" can't be bound, it is needed for command mode, quitting or counts",
+            key
+        ));
+    }
+    Ok(())
+}
+
 pub(crate) fn push_image_path(v: &mut Vec<PathBuf>, p: PathBuf) {
+    if is_image_path(&p) {
+        v.push(p)
+    }
+}
+
+/// Whether the path has the extension of an image riv can display
+pub(crate) fn is_image_path(p: &Path) -> bool {
     if let Some(ext) = p.extension() {
         if let Some(ext_str) = ext.to_str() {
             let low = ext_str.to_string().to_lowercase();
-            if low == "jpg" || low == "jpeg" || low == "png" || low == "bmp" || low == "webp" {
-                v.push(p)
-            }
+            return low == "jpg" || low == "jpeg" || low == "png" || low == "bmp" || low == "webp";
         }
     }
+    false
 }
@@ -0,0 +1,342 @@
+//! # Conflict
+//!
+//! The conflict module decides what happens when an image is moved or copied to a path that is
+//! already taken, and keeps track of what happened to each image.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What to do when a file is already where an image is moved or copied to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    /// Leaves the image where it is
+    Skip,
+    /// Replaces the file
+    Overwrite,
+    /// Keeps both, adding `_1`, `_2` and so on to the name of the image
+    Suffix,
+    /// Replaces the file only if the image was modified more recently
+    KeepNewer,
+    /// Asks what to do for each file
+    Prompt,
+}
+
+impl ConflictPolicy {
+    /// Names of the policies, as accepted on the command line and by `:set conflict=`
+    pub const NAMES: [&'static str; 5] = ["skip", "overwrite", "suffix", "newer", "prompt"];
+
+    /// How to put the image at `src` at `dest`, None when the user has to be asked
+    pub fn resolve(self, src: &Path, dest: &Path) -> Option<Resolution> {
+        self.resolve_among(src, dest, &HashSet::new())
+    }
+
+    /// How to put the image at `src` at `dest`, treating the paths other images are about to
+    /// be written to as taken, None when the user has to be asked
+    pub fn resolve_among(
+        self,
+        src: &Path,
+        dest: &Path,
+        planned: &HashSet<PathBuf>,
+    ) -> Option<Resolution> {
+        if !dest.exists() && !planned.contains(dest) {
+            return Some(Resolution::Write(dest.to_path_buf()));
+        }
+        // Writing an image over itself would destroy it
+        if is_same_file(src, dest) {
+            return Some(Resolution::Skip);
+        }
+        match self {
+            ConflictPolicy::Skip => Some(Resolution::Skip),
+            ConflictPolicy::Overwrite => Some(Resolution::Replace),
+            ConflictPolicy::Suffix => Some(Resolution::Write(free_path_among(dest, planned))),
+            ConflictPolicy::KeepNewer if is_newer(src, dest) => Some(Resolution::Replace),
+            ConflictPolicy::KeepNewer => Some(Resolution::Skip),
+            ConflictPolicy::Prompt => None,
+        }
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "suffix" | "auto-suffix" => Ok(ConflictPolicy::Suffix),
+            "newer" | "keep-newer" => Ok(ConflictPolicy::KeepNewer),
+            "prompt" => Ok(ConflictPolicy::Prompt),
+            _ => Err(format!(
+                "\"{}\" is not a conflict policy, expected one of {}",
+                s,
+                ConflictPolicy::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Suffix => "suffix",
+            ConflictPolicy::KeepNewer => "newer",
+            ConflictPolicy::Prompt => "prompt",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where an image is put
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Written to the path, nothing is there
+    Write(PathBuf),
+    /// Written over the file at the destination
+    Replace,
+    /// Left where it is
+    Skip,
+}
+
+/// What happened to an image that was moved or copied
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// Put at its destination
+    Done,
+    /// Put at the path, as its destination was taken
+    Renamed(PathBuf),
+    /// Put at its destination, replacing the file there
+    Replaced,
+    /// Left where it is, as its destination was taken
+    Skipped,
+    /// Failed with the error
+    Failed(String),
+}
+
+/// Counts of what happened to the images of a move, copy or link
+#[derive(Debug, Default)]
+pub struct Report {
+    done: usize,
+    renamed: usize,
+    replaced: usize,
+    skipped: usize,
+    /// Images copied because they couldn't be linked
+    copied: usize,
+    failures: Vec<String>,
+}
+
+impl Report {
+    /// Records what happened to the image at `path`, printing it only if it failed
+    pub fn record(&mut self, path: &Path, outcome: Outcome) {
+        match outcome {
+            Outcome::Done => self.done += 1,
+            Outcome::Renamed(_) => self.renamed += 1,
+            Outcome::Replaced => self.replaced += 1,
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Failed(e) => {
+                let failure = format!("{}: {}", path.display(), e);
+                eprintln!("{}", failure);
+                self.failures.push(failure);
+            }
+        }
+    }
+
+    /// Records that the image at `path` was copied as it couldn't be linked, warning about it
+    pub fn record_copied(&mut self, path: &Path) {
+        eprintln!(
+            "{}: copied as it can't be linked, such as from another filesystem",
+            path.display()
+        );
+        self.copied += 1;
+    }
+
+    /// Number of images that were put in the destination
+    pub fn written(&self) -> usize {
+        self.done + self.renamed + self.replaced
+    }
+
+    /// Describes the outcomes, `verb` is what was done to the images such as "copied"
+    /// An error is returned if any image failed, describing the first failure
+    pub fn summary(&self, verb: &str, dest: &Path) -> Result<String, String> {
+        let total = self.written() + self.skipped + self.failures.len();
+        let mut details = Vec::new();
+        if self.renamed > 0 {
+            details.push(format!("{} renamed", self.renamed));
+        }
+        if self.replaced > 0 {
+            details.push(format!("{} replaced", self.replaced));
+        }
+        if self.skipped > 0 {
+            details.push(format!("{} skipped", self.skipped));
+        }
+        if self.copied > 0 {
+            details.push(format!("{} copied instead", self.copied));
+        }
+        if self.failures.is_empty() {
+            let mut msg = format!("{} {} image(s) to {}", verb, self.written(), dest.display());
+            if !details.is_empty() {
+                msg.push_str(&format!(" ({})", details.join(", ")));
+            }
+            return Ok(msg);
+        }
+        details.push(format!("{} failed", self.failures.len()));
+        Err(format!(
+            "{} {} of {} images ({}), {}",
+            verb,
+            self.written(),
+            total,
+            details.join(", "),
+            self.failures[0]
+        ))
+    }
+}
+
+/// `path` if nothing is there yet, otherwise the first free path with `_1`, `_2` and so on added
+/// to the file name before its extension
+pub fn free_path(path: &Path) -> PathBuf {
+    free_path_among(path, &HashSet::new())
+}
+
+/// `path` if it is free, otherwise the first free path with `_1`, `_2` and so on added, where
+/// the planned paths are taken as well as the files already there
+fn free_path_among(path: &Path, planned: &HashSet<PathBuf>) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut free = path.to_path_buf();
+    let mut n = 1;
+    while free.exists() || planned.contains(&free) {
+        free = path.with_file_name(format!("{}_{}{}", stem, n, ext));
+        n += 1;
+    }
+    free
+}
+
+/// Whether both paths lead to the same file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether the file at `src` was modified more recently than the file at `dest`
+/// Files without a modification time, such as images inside archives, are never newer
+fn is_newer(src: &Path, dest: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(src), modified(dest)) {
+        (Ok(src), Ok(dest)) => src > dest,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConflictPolicy, Outcome, Report, Resolution};
+    use crate::test_dir::TestDir;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// Temporary directory holding `a.jpg` and `keep/a.jpg`
+    fn conflict_dir(name: &str) -> (TestDir, PathBuf, PathBuf) {
+        let dir = TestDir::new(name);
+        fs::create_dir_all(dir.join("keep")).unwrap();
+        let (src, dest) = (dir.join("a.jpg"), dir.join("keep").join("a.jpg"));
+        fs::write(&src, "src").unwrap();
+        fs::write(&dest, "dest").unwrap();
+        (dir, src, dest)
+    }
+
+    #[test]
+    fn test_policies_resolve_taken_destinations() {
+        let (dir, src, dest) = conflict_dir("conflict");
+        let free = dir.join("keep").join("b.jpg");
+        let resolve = |policy: ConflictPolicy, dest: &Path| policy.resolve(&src, dest);
+
+        assert_eq!(
+            resolve(ConflictPolicy::Prompt, &free),
+            Some(Resolution::Write(free.clone()))
+        );
+        assert_eq!(resolve(ConflictPolicy::Skip, &dest), Some(Resolution::Skip));
+        assert_eq!(
+            resolve(ConflictPolicy::Overwrite, &dest),
+            Some(Resolution::Replace)
+        );
+        assert_eq!(
+            resolve(ConflictPolicy::Suffix, &dest),
+            Some(Resolution::Write(dir.join("keep").join("a_1.jpg")))
+        );
+        assert_eq!(resolve(ConflictPolicy::Prompt, &dest), None);
+        // An image is never written over itself
+        assert_eq!(
+            resolve(ConflictPolicy::Overwrite, &src),
+            Some(Resolution::Skip)
+        );
+    }
+
+    #[test]
+    fn test_planned_destinations_are_taken() {
+        let (dir, src, dest) = conflict_dir("planned");
+        let free = dir.join("keep").join("b.jpg");
+        let planned: HashSet<PathBuf> = vec![free.clone(), dir.join("keep").join("a_1.jpg")]
+            .into_iter()
+            .collect();
+        let resolve =
+            |policy: ConflictPolicy, dest: &Path| policy.resolve_among(&src, dest, &planned);
+
+        assert_eq!(resolve(ConflictPolicy::Skip, &free), Some(Resolution::Skip));
+        assert_eq!(resolve(ConflictPolicy::Prompt, &free), None);
+        assert_eq!(
+            resolve(ConflictPolicy::Suffix, &dest),
+            Some(Resolution::Write(dir.join("keep").join("a_2.jpg")))
+        );
+    }
+
+    #[test]
+    fn test_policies_parse_with_aliases() {
+        for name in ConflictPolicy::NAMES.iter() {
+            let policy: ConflictPolicy = name.parse().unwrap();
+            assert_eq!(&policy.to_string(), name);
+        }
+        assert_eq!("Keep-Newer".parse(), Ok(ConflictPolicy::KeepNewer));
+        assert_eq!("auto-suffix".parse(), Ok(ConflictPolicy::Suffix));
+        assert!("merge".parse::<ConflictPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_report_summarises_outcomes() {
+        let mut report = Report::default();
+        report.record(Path::new("a.jpg"), Outcome::Done);
+        report.record(Path::new("b.jpg"), Outcome::Renamed("b_1.jpg".into()));
+        report.record(Path::new("c.jpg"), Outcome::Skipped);
+        assert_eq!(
+            report.summary("copied", Path::new("keep")),
+            Ok("copied 2 image(s) to keep (1 renamed, 1 skipped)".to_string())
+        );
+
+        report.record_copied(Path::new("a.jpg"));
+        assert_eq!(
+            report.summary("hardlinked", Path::new("keep")),
+            Ok(
+                "hardlinked 2 image(s) to keep (1 renamed, 1 skipped, 1 copied instead)"
+                    .to_string()
+            )
+        );
+
+        report.record(Path::new("d.jpg"), Outcome::Failed("denied".to_string()));
+        assert_eq!(
+            report.summary("moved", Path::new("keep")),
+            Err(
+                "moved 2 of 4 images (1 renamed, 1 skipped, 1 copied instead, 1 failed), d.jpg: denied"
+                    .to_string()
+            )
+        );
+    }
+}
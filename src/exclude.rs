@@ -0,0 +1,122 @@
+//! # Exclude
+//!
+//! The exclude module decides which paths are skipped when searching for images, such as
+//! thumbnail folders or hidden directories.
+
+use glob::{MatchOptions, Pattern};
+use std::path::Path;
+
+/// Exclusions holds the user supplied patterns of paths to skip while searching for images
+#[derive(Debug, Default)]
+pub struct Exclusions {
+    /// Patterns of paths to skip
+    patterns: Vec<Pattern>,
+    /// Whether hidden files and directories should be searched
+    pub include_hidden: bool,
+}
+
+impl Exclusions {
+    /// Creates exclusions from glob patterns
+    /// Patterns without a path separator, like `*_thumb.jpg`, are matched against file names.
+    /// Other patterns, like `**/.thumbnails/**`, are matched against the whole path.
+    pub fn new<S: AsRef<str>>(patterns: &[S], include_hidden: bool) -> Result<Self, String> {
+        let mut exclusions = Self {
+            patterns: Vec::new(),
+            include_hidden,
+        };
+        for pattern in patterns {
+            exclusions.add(pattern.as_ref())?;
+        }
+        Ok(exclusions)
+    }
+
+    /// Adds another pattern of paths to skip
+    pub fn add(&mut self, pattern: &str) -> Result<(), String> {
+        let pattern = Pattern::new(pattern)
+            .map_err(|e| format!("Invalid exclude pattern \"{}\": {}", pattern, e))?;
+        self.patterns.push(pattern);
+        Ok(())
+    }
+
+    /// The patterns currently excluded
+    pub fn patterns(&self) -> Vec<&str> {
+        self.patterns
+            .iter()
+            .map(|pattern| pattern.as_str())
+            .collect()
+    }
+
+    /// Options to search with, hidden files and directories are only matched by wildcards when
+    /// they are included
+    pub fn glob_options(&self) -> MatchOptions {
+        MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: false,
+            require_literal_leading_dot: !self.include_hidden,
+        }
+    }
+
+    /// Whether `path` matches any of the exclude patterns
+    /// `base_dir` allows relative patterns such as `2019/**` to match
+    pub fn is_excluded(&self, path: &Path, base_dir: &Path) -> bool {
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        let file_name = path.file_name().map(Path::new);
+        let relative = path.strip_prefix(base_dir).ok();
+        self.patterns.iter().any(|pattern| {
+            if !pattern.as_str().contains(std::path::is_separator) {
+                return match file_name {
+                    Some(name) => pattern.matches_path_with(name, options),
+                    None => false,
+                };
+            }
+            if pattern.matches_path_with(path, options) {
+                return true;
+            }
+            match relative {
+                Some(rel) => pattern.matches_path_with(rel, options),
+                None => false,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Exclusions;
+    use std::path::Path;
+
+    #[test]
+    fn test_directory_patterns_match_anywhere_in_path() {
+        let exclusions = Exclusions::new(&["**/.thumbnails/**", "**/@eaDir/**"], false).unwrap();
+        let base = Path::new("/home/user/pics");
+        assert!(exclusions.is_excluded(Path::new("/home/user/pics/.thumbnails/a.png"), base));
+        assert!(exclusions.is_excluded(Path::new("/home/user/pics/x/@eaDir/b/c.jpg"), base));
+        assert!(!exclusions.is_excluded(Path::new("/home/user/pics/x/a.png"), base));
+    }
+
+    #[test]
+    fn test_file_name_patterns_only_match_file_names() {
+        let exclusions = Exclusions::new(&["*_thumb.jpg"], false).unwrap();
+        let base = Path::new("/pics");
+        assert!(exclusions.is_excluded(Path::new("/pics/2019/a_thumb.jpg"), base));
+        assert!(!exclusions.is_excluded(Path::new("/pics/a_thumb.jpg/b.jpg"), base));
+        assert!(!exclusions.is_excluded(Path::new("/pics/a.jpg"), base));
+    }
+
+    #[test]
+    fn test_relative_patterns_match_from_base_dir() {
+        let exclusions = Exclusions::new(&["2019/**"], false).unwrap();
+        let base = Path::new("/pics");
+        assert!(exclusions.is_excluded(Path::new("/pics/2019/trip/a.jpg"), base));
+        assert!(!exclusions.is_excluded(Path::new("/pics/2020/2019/a.jpg"), base));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_an_error() {
+        assert!(Exclusions::new(&["[a"], false).is_err());
+    }
+}
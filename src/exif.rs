@@ -0,0 +1,298 @@
+//! # Exif
+//!
+//! The exif module finds the EXIF data of JPEGs and reads details of how photos were taken from
+//! it, such as the camera model and the date the photo was taken.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Marker that starts an EXIF segment in a JPEG
+pub(crate) const APP1: u8 = 0xE1;
+/// How much of a JPEG to read when looking for its EXIF data, EXIF data is limited to 64KiB
+const HEADER_BYTES: u64 = 128 * 1024;
+/// Tag of the camera maker entry
+const MAKE_TAG: u16 = 0x010F;
+/// Tag of the camera model entry
+const MODEL_TAG: u16 = 0x0110;
+/// Tag of the entry holding when the file was last changed
+const DATE_TIME_TAG: u16 = 0x0132;
+/// Tag of the entry pointing at the directory of details about the photo
+const EXIF_DIRECTORY_TAG: u16 = 0x8769;
+/// Tag of the entry holding when the photo was taken, kept in the directory of photo details
+const DATE_TIME_ORIGINAL_TAG: u16 = 0x9003;
+/// EXIF type of text
+const ASCII: u16 = 2;
+/// EXIF type of an unsigned 32 bit value
+const LONG: u16 = 4;
+
+/// Details of how a photo was taken, as recorded in its EXIF data
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tags {
+    /// Maker of the camera
+    pub make: Option<String>,
+    /// Model of the camera
+    pub model: Option<String>,
+    /// When the photo was taken, as `YYYY:MM:DD HH:MM:SS`
+    pub date_taken: Option<String>,
+}
+
+/// The details recorded in the EXIF data of the image at the path
+/// Only JPEGs are read, other images and unreadable files have no details
+pub fn read_tags(path: &Path) -> Tags {
+    match read_header(path) {
+        Some(header) => jpeg_tags(&header),
+        None => Tags::default(),
+    }
+}
+
+/// The details recorded in the EXIF data of the JPEG
+pub fn jpeg_tags(jpeg: &[u8]) -> Tags {
+    let (start, end) = match find_exif(jpeg) {
+        Ok(ExifSegment::Found { start, end }) => (start, end),
+        _ => return Tags::default(),
+    };
+    let tiff = &jpeg[start + 10..end];
+    let ifd = match Ifd::read(tiff) {
+        Some(ifd) => ifd,
+        None => return Tags::default(),
+    };
+    // The date the photo was taken is preferred over the date the file was last changed
+    let date_taken = ifd
+        .sub_directory(tiff, EXIF_DIRECTORY_TAG)
+        .and_then(|details| details.text(tiff, DATE_TIME_ORIGINAL_TAG))
+        .or_else(|| ifd.text(tiff, DATE_TIME_TAG));
+    Tags {
+        make: ifd.text(tiff, MAKE_TAG),
+        model: ifd.text(tiff, MODEL_TAG),
+        date_taken,
+    }
+}
+
+/// Start of the JPEG at the path, long enough to hold its EXIF data
+/// None for other images, images inside archives and unreadable files
+pub(crate) fn read_header(path: &Path) -> Option<Vec<u8>> {
+    if !crate::orientation::is_jpeg(path) || crate::archive::is_entry(path) {
+        return None;
+    }
+    let mut header = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(HEADER_BYTES).read_to_end(&mut header))
+        .ok()?;
+    Some(header)
+}
+
+/// Where the EXIF segment of a JPEG is
+pub(crate) enum ExifSegment {
+    /// The segment starting with its marker at `start` and ending before `end`
+    Found { start: usize, end: usize },
+    /// No EXIF segment, one belongs at `insert_at`
+    Missing { insert_at: usize },
+}
+
+/// Finds the EXIF segment among the segments before the image data
+pub(crate) fn find_exif(jpeg: &[u8]) -> Result<ExifSegment, String> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err("not a JPEG file".to_string());
+    }
+    // EXIF data goes straight after the start of image, or after a JFIF segment
+    let mut insert_at = 2;
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        // Start of scan, the image data follows so there are no more segments to check
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > jpeg.len() {
+            return Err("the JPEG file is truncated".to_string());
+        }
+        if marker == APP1 && jpeg[pos + 4..end].starts_with(b"Exif\0\0") {
+            return Ok(ExifSegment::Found { start: pos, end });
+        }
+        if marker == 0xE0 && pos == 2 {
+            insert_at = end;
+        }
+        pos = end;
+    }
+    Ok(ExifSegment::Missing { insert_at })
+}
+
+/// An APP1 segment holding the TIFF structured EXIF data
+pub(crate) fn exif_segment(tiff: &[u8]) -> Result<Vec<u8>, String> {
+    let length = 2 + 6 + tiff.len();
+    if length > u16::MAX as usize {
+        return Err("the EXIF data is too large".to_string());
+    }
+    let mut segment = vec![0xFF, APP1];
+    segment.extend_from_slice(&(length as u16).to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(tiff);
+    Ok(segment)
+}
+
+/// Byte order of the EXIF data
+#[derive(Clone, Copy)]
+pub(crate) enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    pub(crate) fn u16_at(self, data: &[u8], at: usize) -> Option<u16> {
+        let bytes = [*data.get(at)?, *data.get(at + 1)?];
+        Some(match self {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    pub(crate) fn u32_at(self, data: &[u8], at: usize) -> Option<u32> {
+        let bytes = [
+            *data.get(at)?,
+            *data.get(at + 1)?,
+            *data.get(at + 2)?,
+            *data.get(at + 3)?,
+        ];
+        Some(match self {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    pub(crate) fn u16_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub(crate) fn u32_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Little => value.to_le_bytes(),
+            ByteOrder::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub(crate) fn put_u16(self, data: &mut [u8], at: usize, value: u16) {
+        data[at..at + 2].copy_from_slice(&self.u16_bytes(value));
+    }
+}
+
+/// A directory of entries in the EXIF data
+pub(crate) struct Ifd {
+    pub(crate) byte_order: ByteOrder,
+    /// Offset of the directory from the start of the EXIF data
+    pub(crate) offset: usize,
+    /// Number of entries in the directory
+    pub(crate) count: usize,
+}
+
+impl Ifd {
+    /// The first directory in the EXIF data, where the orientation and camera are kept
+    pub(crate) fn read(tiff: &[u8]) -> Option<Ifd> {
+        let byte_order = match tiff.get(..2)? {
+            b"II" => ByteOrder::Little,
+            b"MM" => ByteOrder::Big,
+            _ => return None,
+        };
+        if byte_order.u16_at(tiff, 2)? != 42 {
+            return None;
+        }
+        let offset = byte_order.u32_at(tiff, 4)? as usize;
+        Ifd::at(tiff, byte_order, offset)
+    }
+
+    /// The directory starting at `offset`
+    fn at(tiff: &[u8], byte_order: ByteOrder, offset: usize) -> Option<Ifd> {
+        let count = byte_order.u16_at(tiff, offset)? as usize;
+        // The entries and the offset of the next directory must all be present
+        if offset + 2 + count * 12 + 4 > tiff.len() {
+            return None;
+        }
+        Some(Ifd {
+            byte_order,
+            offset,
+            count,
+        })
+    }
+
+    /// Offset of each entry in the directory
+    pub(crate) fn entries(&self) -> impl Iterator<Item = usize> {
+        let first = self.offset + 2;
+        (0..self.count).map(move |i| first + i * 12)
+    }
+
+    /// Offset of the entry with the tag and type, None if the directory has none
+    pub(crate) fn find(&self, tiff: &[u8], tag: u16, kind: u16) -> Option<usize> {
+        let byte_order = self.byte_order;
+        self.entries().find(|&entry| {
+            byte_order.u16_at(tiff, entry) == Some(tag)
+                && byte_order.u16_at(tiff, entry + 2) == Some(kind)
+        })
+    }
+
+    /// Text of the entry with the tag, None if it is missing or blank
+    fn text(&self, tiff: &[u8], tag: u16) -> Option<String> {
+        let entry = self.find(tiff, tag, ASCII)?;
+        let length = self.byte_order.u32_at(tiff, entry + 4)? as usize;
+        // Text that fits in the entry is kept in place of the offset to it
+        let start = if length <= 4 {
+            entry + 8
+        } else {
+            self.byte_order.u32_at(tiff, entry + 8)? as usize
+        };
+        let bytes = tiff.get(start..start.checked_add(length)?)?;
+        let bytes = bytes.split(|&b| b == 0).next().unwrap_or(&[]);
+        let text = String::from_utf8_lossy(bytes).trim().to_string();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    /// The directory the entry with the tag points at
+    fn sub_directory(&self, tiff: &[u8], tag: u16) -> Option<Ifd> {
+        let entry = self.find(tiff, tag, LONG)?;
+        let offset = self.byte_order.u32_at(tiff, entry + 8)? as usize;
+        Ifd::at(tiff, self.byte_order, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exif_segment, jpeg_tags, Tags};
+
+    #[test]
+    fn test_camera_and_date_taken_are_read() {
+        // Big endian EXIF data with a make, a model and a pointer to the photo details, which
+        // hold the date the photo was taken
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x03".to_vec();
+        tiff.extend_from_slice(&[0x01, 0x0F, 0, 2, 0, 0, 0, 4, b'r', b'i', b'v', 0]);
+        tiff.extend_from_slice(&[0x01, 0x10, 0, 2, 0, 0, 0, 6, 0, 0, 0, 0x32]);
+        tiff.extend_from_slice(&[0x87, 0x69, 0, 4, 0, 0, 0, 1, 0, 0, 0, 0x38]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff.extend_from_slice(b"Model\0");
+        tiff.extend_from_slice(&[0, 1]);
+        tiff.extend_from_slice(&[0x90, 0x03, 0, 2, 0, 0, 0, 20, 0, 0, 0, 0x4A]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff.extend_from_slice(b"2020:05:17 10:30:00\0");
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend_from_slice(&exif_segment(&tiff).unwrap());
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+
+        let tags = jpeg_tags(&jpeg);
+        assert_eq!(tags.make.as_deref(), Some("riv"));
+        assert_eq!(tags.model.as_deref(), Some("Model"));
+        assert_eq!(tags.date_taken.as_deref(), Some("2020:05:17 10:30:00"));
+    }
+
+    #[test]
+    fn test_jpeg_without_exif_has_no_tags() {
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xDA];
+        assert_eq!(jpeg_tags(&jpeg), Tags::default());
+    }
+}
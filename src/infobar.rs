@@ -18,8 +18,9 @@ pub struct Text {
 impl Text {
     /// Updates the infobar based on the current mode of the application
     /// Normal Mode:
-    ///     mode = index of current image
-    ///     information = path to current image
+    ///     mode = index of current image, number of marked images and progress of images being
+    ///     moved or copied
+    ///     information = path to current image, and the pinned image when comparing
     /// Command Mode:
     ///     mode = "Command"
     ///     information = currently entered user string
@@ -30,18 +31,43 @@ impl Text {
         let (mode, information) = match current_mode {
             Mode::Command(msg) => ("Command".to_string(), format!(":{}", msg)),
             Mode::Normal => {
-                let information = match paths.current_image_path() {
+                let mut information = match paths.current_image_path() {
                     Some(path) => match path.to_str() {
                         Some(name) => name.to_string(),
                         None => "No file".to_string(),
                     },
                     None => "No file selected".to_string(),
                 };
+                // Label which image is which, the pinned image is A
+                if let Some(compare) = &state.compare {
+                    let pinned = compare.pinned.to_string_lossy();
+                    information = match compare.flicker {
+                        None => format!("A: {}  B: {}", pinned, information),
+                        Some(_) if compare.showing_pinned => format!("A: {}", pinned),
+                        Some(_) => format!("B: {}", information),
+                    };
+                }
 
-                let mode = match paths.current_image() {
+                let mut mode = match paths.current_image() {
                     Some(current) => format!("{} of {}", current, paths.max_viewable().unwrap()),
                     None => "No files in path".to_string(),
                 };
+                // Flag marked images with a leading star and show how many are marked
+                if let Some(path) = paths.current_image_path() {
+                    if paths.is_marked(path) {
+                        mode.insert(0, '*');
+                    }
+                }
+                let marked = paths.marked_images().len();
+                if marked > 0 {
+                    mode.push_str(&format!(" ({} marked)", marked));
+                }
+                if let Some(progress) = &state.progress {
+                    mode.push_str(&format!(
+                        "  {} {}/{} (X: cancel)",
+                        progress.verb, progress.done, progress.total
+                    ));
+                }
 
                 (mode, information)
             }
@@ -51,8 +77,45 @@ impl Text {
             }
             Mode::Error(msg) => ("Error".to_string(), msg.to_string()),
             Mode::Success(msg) => ("Success".to_string(), msg.to_string()),
+            Mode::Crop => ("Crop".to_string(), crop_information(paths, state)),
+            Mode::Rename => {
+                let count = state.rename.as_ref().map_or(0, |renames| renames.len());
+                (
+                    "Rename".to_string(),
+                    format!("Rename {} image(s)? Enter: rename  Esc: cancel", count),
+                )
+            }
+            Mode::Conflict(question) => ("Conflict".to_string(), question.to_string()),
+            Mode::Confirm(question) => ("Confirm".to_string(), question.to_string()),
             Mode::Exit => ("Exit".to_string(), "Exiting... Goodbye".to_string()),
         };
         Text { information, mode }
     }
 }
+
+/// Size of the selection and the keys to finish cropping, or the question asked before overwriting
+fn crop_information(paths: &Paths, state: &State) -> String {
+    let crop = match &state.crop {
+        Some(crop) => crop,
+        None => return String::new(),
+    };
+    if crop.confirm_overwrite {
+        let path = paths
+            .current_image_path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        return format!("Overwrite {}? Press O again to confirm", path);
+    }
+    let (mut width, mut height) = (crop.selection.width(), crop.selection.height());
+    if crop.sideways {
+        std::mem::swap(&mut width, &mut height);
+    }
+    let aspect = match crop.aspect {
+        Some((w, h)) => format!("{}:{}", w, h),
+        None => "free".to_string(),
+    };
+    format!(
+        "{}x{} aspect {}  Enter: save copy  O: overwrite  Esc: cancel",
+        width, height, aspect
+    )
+}
@@ -0,0 +1,252 @@
+//! # IPC
+//!
+//! The IPC module provides a local unix socket that lets other programs remote control a running
+//! riv. Clients send one command per line and receive one JSON reply per line.
+
+use serde::Serialize;
+use std::env;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// File name of the socket when no path is supplied
+const DEFAULT_SOCKET_NAME: &str = "riv.sock";
+
+/// Default location of the control socket, `$XDG_RUNTIME_DIR/riv.sock`
+/// Falls back to the temporary directory if `$XDG_RUNTIME_DIR` is not set
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join(DEFAULT_SOCKET_NAME),
+        None => env::temp_dir().join(DEFAULT_SOCKET_NAME),
+    }
+}
+
+/// A command received from a client, to be answered with `IpcServer::reply`
+#[derive(Debug)]
+pub struct Request {
+    /// Index of the client that sent the command
+    client: usize,
+    /// The command string, in the same format as command mode without the leading `:`
+    pub command: String,
+}
+
+/// Reply sent back to a client after each command
+#[derive(Debug, Serialize)]
+pub struct Reply {
+    /// Whether the command succeeded
+    pub ok: bool,
+    /// Success or error message of the command, empty if the command had nothing to report
+    pub message: String,
+    /// Number of the current image counting from 1, as `goto` takes it, None if there are no
+    /// images
+    pub index: Option<usize>,
+    /// Number of images that can be viewed
+    pub total: usize,
+    /// Path of the current image, None if there are no images
+    pub path: Option<String>,
+}
+
+/// A connected client and its partially received input
+struct Client {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    closed: bool,
+}
+
+/// Non-blocking server listening on the control socket
+pub struct IpcServer {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<Client>,
+}
+
+impl IpcServer {
+    /// Listens on the socket at `path`
+    /// A leftover socket from a riv that exited uncleanly is replaced, but a socket another riv is
+    /// still listening on, or a file that isn't a socket, is an error
+    pub fn bind(path: &Path) -> Result<Self, String> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(format!("\"{}\" is not a socket", path.display()));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(format!(
+                    "socket \"{}\" is already in use by another program",
+                    path.display()
+                ));
+            }
+            std::fs::remove_file(path).map_err(|e| e.to_string())?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| e.to_string())?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            clients: Vec::new(),
+        })
+    }
+
+    /// Path the server is listening on
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Accepts new clients and returns every complete command line received since the last poll
+    /// Never blocks
+    pub fn poll(&mut self) -> Vec<Request> {
+        // Clients are only dropped here so that requests from the last poll can still be answered
+        self.clients.retain(|client| !client.closed);
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        eprintln!("Failed to set up remote control client: {}", e);
+                        continue;
+                    }
+                    self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                        closed: false,
+                    });
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Failed to accept remote control client: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut requests = Vec::new();
+        for (index, client) in self.clients.iter_mut().enumerate() {
+            client.read_available();
+            while let Some(line) = client.next_line() {
+                let command = line.trim();
+                if command.is_empty() {
+                    continue;
+                }
+                requests.push(Request {
+                    client: index,
+                    command: command.to_string(),
+                });
+            }
+        }
+        requests
+    }
+
+    /// Sends the reply for `request` as a single line of JSON
+    pub fn reply(&mut self, request: &Request, reply: &Reply) {
+        let client = match self.clients.get_mut(request.client) {
+            Some(client) => client,
+            None => return,
+        };
+        let mut line = match serde_json::to_string(reply) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Failed to serialize remote control reply: {}", e);
+                return;
+            }
+        };
+        line.push('\n');
+        if let Err(e) = client.stream.write_all(line.as_bytes()) {
+            eprintln!("Failed to reply to remote control client: {}", e);
+            client.closed = true;
+        }
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Client {
+    /// Reads everything the client has sent so far without blocking
+    fn read_available(&mut self) {
+        let mut chunk = [0; 1024];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.closed = true;
+                    // Treat a final command without a newline as complete
+                    if !self.buffer.is_empty() && !self.buffer.ends_with(b"\n") {
+                        self.buffer.push(b'\n');
+                    }
+                    return;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("Failed to read from remote control client: {}", e);
+                    self.closed = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the next complete line of input
+    fn next_line(&mut self) -> Option<String> {
+        let end = self.buffer.iter().position(|&b| b == b'\n')?;
+        let line: Vec<u8> = self.buffer.drain(..=end).collect();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IpcServer, Reply};
+    use crate::test_dir::TestDir;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    #[test]
+    fn test_commands_are_received_per_line_and_replied_to_as_json() {
+        let dir = TestDir::new("ipc-socket");
+        let path = dir.join("riv.sock");
+        let mut server = IpcServer::bind(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"next 2\n\n:sort date\nfirst").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        // Give the kernel a moment to deliver the data
+        std::thread::sleep(Duration::from_millis(50));
+
+        let requests = server.poll();
+        let commands: Vec<&str> = requests.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(commands, vec!["next 2", ":sort date", "first"]);
+
+        let reply = Reply {
+            ok: true,
+            message: String::new(),
+            index: Some(2),
+            total: 10,
+            path: Some("a.png".to_string()),
+        };
+        server.reply(&requests[0], &reply);
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert_eq!(
+            line,
+            "{\"ok\":true,\"message\":\"\",\"index\":2,\"total\":10,\"path\":\"a.png\"}\n"
+        );
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_files_that_are_not_sockets_are_left_alone() {
+        let dir = TestDir::new("ipc");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "notes").unwrap();
+
+        assert!(IpcServer::bind(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "notes");
+    }
+}
@@ -0,0 +1,243 @@
+//! # Journal
+//!
+//! The journal module appends every change riv makes to files, such as moving, trashing or
+//! renaming an image, to a log of JSON lines, and reads the log back to show what happened.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A change made to a file, one line of the journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the change was made, as an RFC 3339 timestamp
+    pub time: String,
+    /// When the riv session that made the change started, grouping the changes of a session
+    pub session: String,
+    /// What was done, such as `move` or `trash`
+    pub op: String,
+    /// The file that was changed
+    pub source: PathBuf,
+    /// Where the file was written, for changes that write one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<PathBuf>,
+    /// `ok`, `renamed`, `replaced`, `skipped` or `failed`
+    pub outcome: String,
+    /// Why the change failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Appends changes to the journal file, or does nothing when journaling is off
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// Path of the journal file, None when journaling is off
+    path: Option<PathBuf>,
+    /// When this session started
+    session: String,
+}
+
+impl Journal {
+    /// Journal appending to the file at the path, None turns journaling off
+    pub fn new(path: Option<PathBuf>) -> Journal {
+        Journal {
+            path,
+            session: Local::now().to_rfc3339(),
+        }
+    }
+
+    /// Records a change that was made successfully
+    pub fn ok(&self, op: &str, source: &Path, destination: Option<&Path>) {
+        self.record(op, source, destination, "ok", None);
+    }
+
+    /// Records a change that failed with the error
+    pub fn failed(&self, op: &str, source: &Path, destination: Option<&Path>, error: &str) {
+        self.record(op, source, destination, "failed", Some(error));
+    }
+
+    /// Where the files trashed in every session recorded in the journal were trashed from
+    /// Nothing was trashed when journaling is off or the journal can't be read
+    pub fn trashed(&self) -> HashSet<PathBuf> {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return HashSet::new(),
+        };
+        match read(path) {
+            Ok(entries) => entries
+                .into_iter()
+                .filter(|entry| entry.op == "trash" && entry.outcome == "ok")
+                .map(|entry| entry.source)
+                .collect(),
+            Err(e) => {
+                eprintln!("Failed to read journal: {}", e);
+                HashSet::new()
+            }
+        }
+    }
+
+    /// Records a change and how it turned out
+    /// Failing to write the journal is reported without stopping the change being made
+    pub fn record(
+        &self,
+        op: &str,
+        source: &Path,
+        destination: Option<&Path>,
+        outcome: &str,
+        error: Option<&str>,
+    ) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let entry = Entry {
+            time: Local::now().to_rfc3339(),
+            session: self.session.clone(),
+            op: op.to_string(),
+            source: absolute(source),
+            destination: destination.map(absolute),
+            outcome: outcome.to_string(),
+            error: error.map(str::to_string),
+        };
+        if let Err(e) = append(path, &entry) {
+            eprintln!("Failed to write to journal {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Default location of the journal, `$XDG_STATE_HOME/riv/journal.jsonl`
+/// Falls back to `~/.local/state/riv/journal.jsonl` if `$XDG_STATE_HOME` is not set
+pub fn default_journal_path() -> Option<PathBuf> {
+    let state_dir = crate::xdg_dir("XDG_STATE_HOME", ".local/state")?;
+    Some(state_dir.join("riv").join("journal.jsonl"))
+}
+
+/// Prints what happened in each session recorded in the journal at the path, in order
+pub fn replay(path: &Path) -> Result<(), String> {
+    let entries = read(path)?;
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for line in replay_lines(&entries) {
+        writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The entries of the journal at the path, lines that can't be read are skipped with a warning
+pub fn read(path: &Path) -> Result<Vec<Entry>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => eprintln!("Skipping line {} of {}: {}", i + 1, path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+/// Lines describing the entries, a heading for each session followed by its changes
+fn replay_lines(entries: &[Entry]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut session = None;
+    for entry in entries {
+        if session != Some(&entry.session) {
+            session = Some(&entry.session);
+            lines.push(format!("Session started {}", entry.session));
+        }
+        // Only the time of day is shown, the session heading has the date
+        let time = DateTime::parse_from_rfc3339(&entry.time)
+            .map(|time| time.format("%H:%M:%S").to_string())
+            .unwrap_or_else(|_| entry.time.clone());
+        let mut line = format!("  {} {} {}", time, entry.op, entry.source.display());
+        if let Some(destination) = &entry.destination {
+            line.push_str(&format!(" -> {}", destination.display()));
+        }
+        match (&entry.outcome[..], &entry.error) {
+            ("ok", _) => {}
+            (outcome, Some(error)) => line.push_str(&format!(" ({}: {})", outcome, error)),
+            (outcome, None) => line.push_str(&format!(" ({})", outcome)),
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Appends the entry as a line of JSON, creating the journal and its directory if needed
+fn append(path: &Path, entry: &Entry) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    // Written in one call so entries of several riv instances don't interleave
+    file.write_all(line.as_bytes()).map_err(|e| e.to_string())
+}
+
+/// The path made absolute, so the journal doesn't depend on where riv was started
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, replay_lines, Journal};
+    use crate::test_dir::TestDir;
+    use std::path::Path;
+
+    #[test]
+    fn test_changes_are_appended_and_replayed() {
+        let dir = TestDir::new("journal");
+        let path = dir.join("state").join("journal.jsonl");
+        let journal = Journal::new(Some(path.clone()));
+        journal.ok("move", Path::new("/a.jpg"), Some(Path::new("/keep/a.jpg")));
+        journal.failed("delete", Path::new("/b.jpg"), None, "denied");
+        journal.record("copy", Path::new("/c.jpg"), None, "skipped", None);
+        journal.ok("trash", Path::new("/d.jpg"), None);
+        journal.failed("trash", Path::new("/e.jpg"), None, "denied");
+        let entries = read(&path).unwrap();
+        let trashed = journal.trashed();
+
+        assert_eq!(entries.len(), 5);
+        assert_eq!(
+            trashed.into_iter().collect::<Vec<_>>(),
+            vec![Path::new("/d.jpg")]
+        );
+        assert_eq!(
+            entries[0].destination.as_deref(),
+            Some(Path::new("/keep/a.jpg"))
+        );
+        assert_eq!(entries[1].error.as_deref(), Some("denied"));
+        let lines = replay_lines(&entries[..3]);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Session started "));
+        assert!(lines[1].ends_with(" move /a.jpg -> /keep/a.jpg"));
+        assert!(lines[2].ends_with(" delete /b.jpg (failed: denied)"));
+        assert!(lines[3].ends_with(" copy /c.jpg (skipped)"));
+    }
+
+    #[test]
+    fn test_nothing_is_written_when_journaling_is_off() {
+        // Would panic on writing to a path that isn't there
+        Journal::new(None).ok("trash", Path::new("/a.jpg"), None);
+    }
+}
@@ -17,17 +17,33 @@ extern crate regex;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod archive;
+pub mod bookmarks;
 pub mod cli;
+pub mod conflict;
+pub mod exclude;
+pub mod exif;
 pub mod infobar;
+#[cfg(unix)]
+pub mod ipc;
+pub mod journal;
+pub mod list;
+pub mod orientation;
 pub mod paths;
 pub mod program;
 pub mod screen;
+pub mod session;
 pub mod sort;
+pub mod template;
+#[cfg(test)]
+mod test_dir;
+pub mod trashcan;
 pub mod ui;
 
 use regex::Regex;
 use shellexpand::full;
-use std::path::PathBuf;
+use std::env;
+use std::path::{Component, PathBuf};
 
 /// Converts the provided path by user to a path that can be glob'd, note this function takes the
 /// current_directory in order to handle relative paths
@@ -79,7 +95,6 @@ pub fn path_to_glob(current_dir: &PathBuf, path: &str) -> Result<PathBuf, String
 /// the path to exist, causing all globbing to fail and having to remove the glob, normalize then add it back on
 fn normalize_path(path: PathBuf) -> PathBuf {
     use std::fs::read_link;
-    use std::path::Component;
 
     let mut normalized = PathBuf::new();
     for component in path.components() {
@@ -112,3 +127,124 @@ pub fn new_base_dir(path: &PathBuf) -> Result<PathBuf, String> {
         path.display()
     ))
 }
+
+/// Finds the deepest directory that contains all of the given paths
+/// Returns None if no paths are given or they share no ancestor, such as different drives on
+/// Windows
+pub fn common_ancestor(paths: &[PathBuf]) -> Option<PathBuf> {
+    let (first, rest) = paths.split_first()?;
+    let mut common: Vec<Component> = first.components().collect();
+    for path in rest {
+        let shared = common
+            .iter()
+            .zip(path.components())
+            .take_while(|(a, b)| *a == b)
+            .count();
+        common.truncate(shared);
+    }
+    if common.is_empty() {
+        return None;
+    }
+    Some(common.iter().collect())
+}
+
+/// Splits user input into separate paths or globs on unescaped whitespace
+/// Whitespace can be kept in a path by escaping it with a backslash on Unix, or by surrounding
+/// the path in single or double quotes
+pub fn split_paths(input: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut current = String::new();
+    let mut in_path = false;
+    let mut quote: Option<char> = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), '\\') => {
+                // path_to_glob removes escapes on Unix, so keep quoted backslashes literal
+                if cfg!(unix) {
+                    current.push('\\');
+                }
+                current.push('\\');
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_path = true;
+            }
+            (None, '\\') if cfg!(unix) => {
+                // Keep the escape, it is removed by path_to_glob
+                current.push('\\');
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+                in_path = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_path {
+                    paths.push(current.clone());
+                    current.clear();
+                    in_path = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_path = true;
+            }
+        }
+    }
+    if in_path {
+        paths.push(current);
+    }
+    paths
+}
+
+/// The XDG base directory named by the environment variable `var`, such as `XDG_STATE_HOME`
+/// Falls back to `home_fallback` in the home directory if the variable is not set or empty
+pub fn xdg_dir(var: &str, home_fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(home_fallback)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_ancestor, split_paths};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_common_ancestor_of_sibling_directories_is_their_parent() {
+        let paths = vec![
+            PathBuf::from("/home/user/pics/2019"),
+            PathBuf::from("/home/user/pics/2020/trip"),
+            PathBuf::from("/home/user/pics"),
+        ];
+        assert_eq!(
+            common_ancestor(&paths),
+            Some(PathBuf::from("/home/user/pics"))
+        );
+    }
+
+    #[test]
+    fn test_common_ancestor_of_no_paths_is_none() {
+        assert_eq!(common_ancestor(&[]), None);
+    }
+
+    #[test]
+    fn test_split_paths_on_whitespace() {
+        assert_eq!(
+            split_paths("  a/*.png b/   \"c d/**/*.jpg\" 'e f'"),
+            vec!["a/*.png", "b/", "c d/**/*.jpg", "e f"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_split_paths_keeps_escaped_whitespace() {
+        assert_eq!(
+            split_paths("folder\\ path/*.png other"),
+            vec!["folder\\ path/*.png", "other"]
+        );
+    }
+}
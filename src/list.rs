@@ -0,0 +1,187 @@
+//! # List
+//!
+//! The list module prints the images riv would display, in the order it would display them,
+//! without opening a window. This lets scripts reuse the discovery and sorting of images.
+
+use crate::cli::Args;
+use crate::sort::Sorter;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Formats the image list can be printed in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListFormat {
+    /// One path per line
+    Plain,
+    /// A JSON array with the size, date and dimensions of each image
+    Json,
+}
+
+/// Information about an image printed in the JSON list
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    /// Path of the image
+    pub path: String,
+    /// Size of the file in bytes
+    pub size: Option<u64>,
+    /// Last modification time in seconds since the unix epoch
+    pub modified: Option<u64>,
+    /// Width of the image in pixels
+    pub width: Option<u32>,
+    /// Height of the image in pixels
+    pub height: Option<u32>,
+}
+
+impl Entry {
+    /// Reads the details of the image at `path`, details that can't be read are left as None
+    /// Images inside archives report the modification time of the archive
+    pub fn new(path: &Path) -> Self {
+        let metadata = crate::archive::containing_file(path).metadata().ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        let size = if crate::archive::is_entry(path) {
+            crate::archive::read_entry(path)
+                .ok()
+                .map(|bytes| bytes.len() as u64)
+        } else {
+            metadata.map(|m| m.len())
+        };
+        // Only the header of the image is read to get the dimensions
+        let dimensions = crate::archive::image_dimensions(path);
+        let (width, height) = match dimensions {
+            Some((width, height)) => (Some(width), Some(height)),
+            None => (None, None),
+        };
+        Self {
+            path: path.to_string_lossy().into_owned(),
+            size,
+            modified,
+            width,
+            height,
+        }
+    }
+}
+
+/// Sorts the images and caps them at `max`, giving the images riv would display in order
+/// A `max` of 0 means no cap
+pub fn resolve(mut images: Vec<PathBuf>, sorter: &Sorter, max: usize) -> Vec<PathBuf> {
+    sorter.sort(&mut images);
+    if max != 0 {
+        images.truncate(max);
+    }
+    images
+}
+
+/// Prints the images found from the command line arguments to stdout in the given format
+pub fn print(args: Args, format: ListFormat) -> Result<(), String> {
+    let sorter = Sorter::new(args.sort_order, args.reverse);
+    let images = resolve(args.files, &sorter, args.max_length);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = match format {
+        ListFormat::Plain => images
+            .iter()
+            .try_for_each(|path| writeln!(out, "{}", path.display())),
+        ListFormat::Json => {
+            let entries: Vec<Entry> = images.iter().map(|path| Entry::new(path)).collect();
+            serde_json::to_writer_pretty(&mut out, &entries)
+                .map_err(io::Error::from)
+                .and_then(|_| writeln!(out))
+        }
+    };
+    match result {
+        Ok(()) => Ok(()),
+        // The reader went away, such as when piping into head, which isn't an error
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, Entry};
+    use crate::cli::discover_images;
+    use crate::exclude::Exclusions;
+    use crate::sort::{SortOrder, Sorter};
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_discovered_images_are_filtered_sorted_and_capped() {
+        let dir = TestDir::new("list-discover");
+        for name in &["c.PNG", "a.jpg", "b.webp", "notes.txt", "d.bmp"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let (files, base_dir) =
+            discover_images(&dir, &[dir.to_str().unwrap()], &Exclusions::default()).unwrap();
+        assert_eq!(base_dir, *dir);
+        let sorter = Sorter::new(SortOrder::Alphabetical, false);
+        let images = resolve(files, &sorter, 3);
+        let names: Vec<_> = images
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.jpg", "b.webp", "c.PNG"]);
+    }
+
+    #[test]
+    fn test_images_from_overlapping_paths_are_merged_without_duplicates() {
+        let dir = TestDir::new("list-merge");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+        fs::write(dir.join("a").join("1.png"), b"").unwrap();
+        fs::write(dir.join("b").join("2.png"), b"").unwrap();
+
+        let paths = ["a/*.png", "b/", "a/../a/1.png"];
+        let (files, base_dir) = discover_images(&dir, &paths, &Exclusions::default()).unwrap();
+        assert_eq!(base_dir, *dir);
+        assert_eq!(
+            files,
+            vec![dir.join("a").join("1.png"), dir.join("b").join("2.png")]
+        );
+    }
+
+    #[test]
+    fn test_excluded_and_hidden_images_are_skipped() {
+        let dir = TestDir::new("list-exclude");
+        for sub in &[".hidden", "@eaDir", "trip"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join("a.png"), b"").unwrap();
+        }
+        fs::write(dir.join("trip").join("a_thumb.jpg"), b"").unwrap();
+
+        let exclusions = Exclusions::new(&["**/@eaDir/**", "*_thumb.jpg"], false).unwrap();
+        let (files, _) = discover_images(&dir, &["**/*"], &exclusions).unwrap();
+        assert_eq!(files, vec![dir.join("trip").join("a.png")]);
+
+        let exclusions = Exclusions::new(&["**/@eaDir/**", "*_thumb.jpg"], true).unwrap();
+        let (mut files, _) = discover_images(&dir, &["**/*"], &exclusions).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                dir.join(".hidden").join("a.png"),
+                dir.join("trip").join("a.png")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entry_reads_size_and_dimensions() {
+        let dir = TestDir::new("list-entry");
+        let path = dir.join("image.png");
+        image::RgbImage::new(3, 2).save(&path).unwrap();
+
+        let entry = Entry::new(&path);
+        assert_eq!(entry.size, Some(fs::metadata(&path).unwrap().len()));
+        assert!(entry.modified.is_some());
+        assert_eq!((entry.width, entry.height), (Some(3), Some(2)));
+    }
+}
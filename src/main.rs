@@ -1,4 +1,6 @@
 use riv::cli::cli;
+use riv::journal;
+use riv::list;
 use riv::program::Program;
 use std::convert::TryInto;
 
@@ -6,6 +8,16 @@ const INITIAL_TITLE: &str = "riv";
 
 fn main() -> Result<(), String> {
     let args = cli()?;
+    // Replaying the journal and listing images don't need a window, so don't initialise SDL
+    if args.replay_journal {
+        let path = args
+            .journal
+            .ok_or("no journal to replay, journaling is disabled")?;
+        return journal::replay(&path);
+    }
+    if let Some(format) = args.list {
+        return list::print(args, format);
+    }
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
     let sdl_context = sdl2::init()?;
     let video = sdl_context.video()?;
@@ -0,0 +1,317 @@
+//! # Orientation
+//!
+//! The orientation module writes the rotation and flips of the view back to image files. JPEGs
+//! are changed losslessly by updating the EXIF orientation tag, other formats are re-encoded with
+//! their pixels turned.
+
+use crate::exif::{exif_segment, find_exif, read_header, ExifSegment, Ifd};
+use crate::ui::RotAngle;
+use image::{DynamicImage, ImageFormat};
+use std::path::Path;
+
+/// Tag of the orientation entry in the EXIF data
+const ORIENTATION_TAG: u16 = 0x0112;
+/// EXIF type of an unsigned 16 bit value
+const SHORT: u16 = 3;
+
+/// A turn and mirror applied to an image, the image is mirrored horizontally before it is turned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orientation {
+    /// Quarter turns clockwise
+    pub rotation: RotAngle,
+    /// Mirrored left to right
+    pub mirrored: bool,
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation {
+            rotation: RotAngle::Up,
+            mirrored: false,
+        }
+    }
+}
+
+impl Orientation {
+    /// The orientation the image is displayed in with the given rotation and flips
+    pub fn from_view(rotation: RotAngle, flip_horizontal: bool, flip_vertical: bool) -> Self {
+        // A vertical flip is a horizontal flip followed by a half turn
+        if flip_vertical {
+            Orientation {
+                rotation: rotation.rot_clockwise().rot_clockwise(),
+                mirrored: !flip_horizontal,
+            }
+        } else {
+            Orientation {
+                rotation,
+                mirrored: flip_horizontal,
+            }
+        }
+    }
+
+    /// The orientation described by an EXIF orientation value, unknown values are upright
+    pub fn from_exif(value: u16) -> Self {
+        let (rotation, mirrored) = match value {
+            2 => (RotAngle::Up, true),
+            3 => (RotAngle::Down, false),
+            4 => (RotAngle::Down, true),
+            5 => (RotAngle::Left, true),
+            6 => (RotAngle::Right, false),
+            7 => (RotAngle::Right, true),
+            8 => (RotAngle::Left, false),
+            _ => (RotAngle::Up, false),
+        };
+        Orientation { rotation, mirrored }
+    }
+
+    /// The EXIF orientation value describing this orientation
+    pub fn exif(self) -> u16 {
+        match (self.rotation, self.mirrored) {
+            (RotAngle::Up, false) => 1,
+            (RotAngle::Up, true) => 2,
+            (RotAngle::Down, false) => 3,
+            (RotAngle::Down, true) => 4,
+            (RotAngle::Left, true) => 5,
+            (RotAngle::Right, false) => 6,
+            (RotAngle::Right, true) => 7,
+            (RotAngle::Left, false) => 8,
+        }
+    }
+
+    /// Quarter turns clockwise
+    pub fn quarter_turns(self) -> u8 {
+        match self.rotation {
+            RotAngle::Up => 0,
+            RotAngle::Right => 1,
+            RotAngle::Down => 2,
+            RotAngle::Left => 3,
+        }
+    }
+
+    /// This orientation followed by `after`, such as the orientation a file is shown in with the
+    /// turns and flips of the view on top
+    pub fn then(self, after: Orientation) -> Self {
+        // Mirroring after a turn is the same as mirroring first and turning the other way
+        let turns = if after.mirrored {
+            4 - self.quarter_turns()
+        } else {
+            self.quarter_turns()
+        };
+        let rotation = match (turns + after.quarter_turns()) % 4 {
+            0 => RotAngle::Up,
+            1 => RotAngle::Right,
+            2 => RotAngle::Down,
+            _ => RotAngle::Left,
+        };
+        Orientation {
+            rotation,
+            mirrored: self.mirrored != after.mirrored,
+        }
+    }
+
+    /// Whether the image is shown as it is stored
+    pub fn is_upright(self) -> bool {
+        self == Orientation::default()
+    }
+
+    /// Turns and mirrors the pixels of the image
+    pub fn apply(self, image: DynamicImage) -> DynamicImage {
+        let image = if self.mirrored { image.fliph() } else { image };
+        match self.rotation {
+            RotAngle::Up => image,
+            RotAngle::Right => image.rotate90(),
+            RotAngle::Down => image.rotate180(),
+            RotAngle::Left => image.rotate270(),
+        }
+    }
+}
+
+/// Whether the image at the path is a JPEG, going by its extension
+pub fn is_jpeg(path: &Path) -> bool {
+    matches!(ImageFormat::from_path(path), Ok(ImageFormat::Jpeg))
+}
+
+/// The orientation the image at the path should be displayed in
+/// Only JPEGs record an orientation, other images and unreadable files are upright
+pub fn read_orientation(path: &Path) -> Orientation {
+    match read_header(path).and_then(|header| jpeg_orientation(&header)) {
+        Some(value) => Orientation::from_exif(value),
+        None => Orientation::default(),
+    }
+}
+
+/// The EXIF orientation value of the JPEG, None if it doesn't have one
+pub fn jpeg_orientation(jpeg: &[u8]) -> Option<u16> {
+    let (start, end) = match find_exif(jpeg).ok()? {
+        ExifSegment::Found { start, end } => (start, end),
+        ExifSegment::Missing { .. } => return None,
+    };
+    let tiff = &jpeg[start + 10..end];
+    let ifd = Ifd::read(tiff)?;
+    let entry = ifd.find(tiff, ORIENTATION_TAG, SHORT)?;
+    ifd.byte_order.u16_at(tiff, entry + 8)
+}
+
+/// Sets the EXIF orientation value of the JPEG, adding EXIF data if it has none
+/// The image data itself is left untouched
+pub fn set_jpeg_orientation(jpeg: &[u8], value: u16) -> Result<Vec<u8>, String> {
+    let (start, end) = match find_exif(jpeg)? {
+        ExifSegment::Found { start, end } => (start, end),
+        ExifSegment::Missing { insert_at } => {
+            let mut out = Vec::with_capacity(jpeg.len() + 40);
+            out.extend_from_slice(&jpeg[..insert_at]);
+            out.extend_from_slice(&exif_segment(&minimal_tiff(value))?);
+            out.extend_from_slice(&jpeg[insert_at..]);
+            return Ok(out);
+        }
+    };
+    let mut tiff = jpeg[start + 10..end].to_vec();
+    let ifd = Ifd::read(&tiff).ok_or_else(|| "the EXIF data is corrupt".to_string())?;
+    match ifd.find(&tiff, ORIENTATION_TAG, SHORT) {
+        Some(entry) => ifd.byte_order.put_u16(&mut tiff, entry + 8, value),
+        None => append_with_orientation(&ifd, &mut tiff, value),
+    }
+    let mut out = Vec::with_capacity(jpeg.len() + 16);
+    out.extend_from_slice(&jpeg[..start]);
+    out.extend_from_slice(&exif_segment(&tiff)?);
+    out.extend_from_slice(&jpeg[end..]);
+    Ok(out)
+}
+
+/// EXIF data holding only an orientation
+fn minimal_tiff(value: u16) -> Vec<u8> {
+    let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+    tiff.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    tiff.extend_from_slice(&SHORT.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&value.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // No further directories
+    tiff.extend_from_slice(&[0, 0, 0, 0]);
+    tiff
+}
+
+/// Writes a copy of the directory with an orientation entry added to the end of the EXIF data
+/// and points the EXIF header at it
+/// Values the other entries point to are left where they are, so their offsets stay valid
+fn append_with_orientation(ifd: &Ifd, tiff: &mut Vec<u8>, value: u16) {
+    let order = ifd.byte_order;
+    let mut entries: Vec<Vec<u8>> = ifd
+        .entries()
+        .map(|entry| tiff[entry..entry + 12].to_vec())
+        .collect();
+    let mut orientation = Vec::with_capacity(12);
+    orientation.extend_from_slice(&order.u16_bytes(ORIENTATION_TAG));
+    orientation.extend_from_slice(&order.u16_bytes(SHORT));
+    orientation.extend_from_slice(&order.u32_bytes(1));
+    orientation.extend_from_slice(&order.u16_bytes(value));
+    orientation.extend_from_slice(&[0, 0]);
+    // Entries are kept sorted by tag
+    let position = entries
+        .iter()
+        .position(|entry| order.u16_at(entry, 0).unwrap_or(0) > ORIENTATION_TAG)
+        .unwrap_or(entries.len());
+    entries.insert(position, orientation);
+    let next_at = ifd.offset + 2 + ifd.count * 12;
+    let next = tiff[next_at..next_at + 4].to_vec();
+
+    // Directories start on a word boundary
+    if tiff.len() % 2 == 1 {
+        tiff.push(0);
+    }
+    let new_offset = tiff.len() as u32;
+    tiff.extend_from_slice(&order.u16_bytes(entries.len() as u16));
+    for entry in entries {
+        tiff.extend_from_slice(&entry);
+    }
+    tiff.extend_from_slice(&next);
+    tiff[4..8].copy_from_slice(&order.u32_bytes(new_offset));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{jpeg_orientation, set_jpeg_orientation, Orientation};
+    use crate::ui::RotAngle;
+    use image::{DynamicImage, GenericImageView, RgbImage};
+
+    /// Start of image, a JFIF segment, then the start of the image data
+    const JPEG: &[u8] = &[
+        0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x06, b'J', b'F', b'I', b'F', 0xFF, 0xDA, 0x01, 0x02,
+    ];
+
+    #[test]
+    fn test_exif_values_match_view_orientations() {
+        for value in 1..=8 {
+            assert_eq!(Orientation::from_exif(value).exif(), value);
+        }
+        // Flipping vertically mirrors and turns the image half way
+        let flipped = Orientation::from_view(RotAngle::Up, false, true);
+        assert_eq!(flipped.exif(), 4);
+        let both = Orientation::from_view(RotAngle::Right, true, true);
+        assert_eq!(both.exif(), 8);
+    }
+
+    #[test]
+    fn test_orientation_is_added_and_updated_leaving_image_data_alone() {
+        assert_eq!(jpeg_orientation(JPEG), None);
+
+        let added = set_jpeg_orientation(JPEG, 6).unwrap();
+        assert_eq!(jpeg_orientation(&added), Some(6));
+        // The JFIF segment stays first and the image data is untouched
+        assert_eq!(&added[..10], &JPEG[..10]);
+        assert!(added.ends_with(&JPEG[10..]));
+
+        let updated = set_jpeg_orientation(&added, 3).unwrap();
+        assert_eq!(jpeg_orientation(&updated), Some(3));
+        assert_eq!(updated.len(), added.len());
+    }
+
+    #[test]
+    fn test_orientation_is_added_to_existing_exif_data() {
+        // Little endian EXIF data with a single make entry pointing at a value after it
+        let mut tiff = b"II\x2a\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&[0x0F, 0x01, 0x02, 0x00, 0x04, 0, 0, 0, 0x1A, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff.extend_from_slice(b"riv\0");
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend_from_slice(&crate::exif::exif_segment(&tiff).unwrap());
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+
+        let with_orientation = set_jpeg_orientation(&jpeg, 8).unwrap();
+        assert_eq!(jpeg_orientation(&with_orientation), Some(8));
+        // The make entry still points at its value
+        let make = &with_orientation[12 + 0x1A..12 + 0x1A + 4];
+        assert_eq!(make, b"riv\0");
+    }
+
+    #[test]
+    fn test_apply_mirrors_before_turning() {
+        let mut pixels = RgbImage::new(2, 1);
+        pixels.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        let orientation = Orientation {
+            rotation: RotAngle::Right,
+            mirrored: true,
+        };
+        let turned = orientation.apply(DynamicImage::ImageRgb8(pixels));
+        assert_eq!(turned.dimensions(), (1, 2));
+        // Mirrored the red pixel is on the right, turned clockwise it ends up at the bottom
+        assert_eq!(turned.get_pixel(0, 1).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_then_applies_both_orientations_in_order() {
+        let mut pixels = RgbImage::new(3, 2);
+        for (x, y, pixel) in pixels.enumerate_pixels_mut() {
+            *pixel = image::Rgb([x as u8, y as u8, 0]);
+        }
+        let pixels = DynamicImage::ImageRgb8(pixels);
+        for first in (1..=8).map(Orientation::from_exif) {
+            for after in (1..=8).map(Orientation::from_exif) {
+                let both = after.apply(first.apply(pixels.clone()));
+                let combined = first.then(after).apply(pixels.clone());
+                assert_eq!(combined.dimensions(), both.dimensions());
+                assert_eq!(combined.to_bytes(), both.to_bytes());
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Paths contains the Paths struct which contains all path related information required for the
 //! running of the program.
 
+use std::collections::HashSet;
 use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::slice::SliceIndex;
@@ -67,6 +68,7 @@ impl PathsBuilder {
             index: self.index,
             art_len: self.art_len,
             art_len_orig: self.art_len_orig,
+            marked: HashSet::new(),
         }
     }
 }
@@ -86,6 +88,8 @@ pub struct Paths {
     art_len: usize,
     /// Original Artificial length of the vector
     art_len_orig: Option<usize>,
+    /// marked is the set of image paths the user has marked for bulk operations
+    marked: HashSet<PathBuf>,
 }
 
 impl Paths {
@@ -247,8 +251,9 @@ impl Paths {
         };
 
         assert!(index < len);
-        // Remove image
-        self.images.remove(index);
+        // Remove image, a removed image can no longer be marked
+        let removed = self.images.remove(index);
+        self.marked.remove(&removed);
         // Decrease artificial length
         self.art_len = self.art_len.saturating_sub(1);
 
@@ -266,6 +271,55 @@ impl Paths {
         }
     }
 
+    /// Stops tracking every image `keep` returns false for
+    /// The current image stays current if it is kept, otherwise the next kept image becomes current
+    pub fn retain<F: FnMut(&PathBuf) -> bool>(&mut self, mut keep: F) {
+        let current = self.index;
+        let mut new_index = None;
+        let mut removed_viewable = 0;
+        let mut kept = Vec::with_capacity(self.images.len());
+        for (i, path) in self.images.drain(..).enumerate() {
+            if keep(&path) {
+                if new_index.is_none() && matches!(current, Some(c) if i >= c) {
+                    new_index = Some(kept.len());
+                }
+                kept.push(path);
+            } else {
+                if i < self.art_len {
+                    removed_viewable += 1;
+                }
+                self.marked.remove(&path);
+            }
+        }
+        self.images = kept;
+        // Removed images shrink the viewable images just like removing them one at a time
+        self.art_len = self.art_len.saturating_sub(removed_viewable);
+
+        self.index = if self.art_len == 0 {
+            None
+        } else {
+            match new_index {
+                Some(index) if index < self.art_len => Some(index),
+                // Nothing was kept after the current image, fall back to the last viewable one
+                _ => Some(self.art_len - 1),
+            }
+        };
+    }
+
+    /// Replaces the path of a tracked image that was renamed, keeping its position and mark
+    /// Returns false if `old` isn't tracked
+    pub fn rename_image(&mut self, old: &PathBuf, new: PathBuf) -> bool {
+        let index = match self.images.iter().position(|path| path == old) {
+            Some(index) => index,
+            None => return false,
+        };
+        if self.marked.remove(old) {
+            self.marked.insert(new.clone());
+        }
+        self.images[index] = new;
+        true
+    }
+
     /// Removes current image from tracking
     pub fn remove_current_image(&mut self) {
         // Check if there are any images to remove
@@ -301,6 +355,69 @@ impl Paths {
         }
     }
 
+    /// Toggles whether the image at `index` is marked
+    /// Returns the new marked state of the image, false if no image is at `index`
+    pub fn toggle_mark(&mut self, index: usize) -> bool {
+        let path = match self.images.get(index) {
+            Some(path) => path,
+            None => return false,
+        };
+        if self.marked.remove(path) {
+            false
+        } else {
+            self.marked.insert(path.clone());
+            true
+        }
+    }
+
+    /// Whether the image at `path` is marked
+    pub fn is_marked(&self, path: &PathBuf) -> bool {
+        self.marked.contains(path)
+    }
+
+    /// Marked images in the order they are currently displayed
+    /// Marked images past the maximum viewable are not included
+    pub fn marked_images(&self) -> Vec<PathBuf> {
+        let max_viewable = match self.max_viewable() {
+            Some(max) => max,
+            None => return Vec::new(),
+        };
+        self.images[..max_viewable]
+            .iter()
+            .filter(|path| self.marked.contains(*path))
+            .cloned()
+            .collect()
+    }
+
+    /// Every marked image, including those past the maximum viewable, in the order they are
+    /// displayed
+    pub fn marks(&self) -> Vec<PathBuf> {
+        self.images
+            .iter()
+            .filter(|path| self.marked.contains(*path))
+            .cloned()
+            .collect()
+    }
+
+    /// Marks the image at `path`, returning false if it isn't one of the images
+    pub fn mark(&mut self, path: &PathBuf) -> bool {
+        if !self.images.contains(path) {
+            return false;
+        }
+        self.marked.insert(path.clone());
+        true
+    }
+
+    /// Unmarks all images
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+
+    /// Maximum number of images to display at once set by the user, 0 if there is none
+    pub fn maximum(&self) -> usize {
+        self.art_len_orig.unwrap_or(0)
+    }
+
     /// Update at runtime the maximum images to display at once
     /// Updates index to `actual_max_viewable` if index is too large
     pub fn set_actual_maximum(&mut self, art_max: usize) {
@@ -510,6 +627,63 @@ mod tests {
         assert_eq!(images.max_viewable_index(), Some(9));
     }
 
+    #[test]
+    fn test_retain_keeps_current_image_or_moves_to_next_kept() {
+        let images: Vec<PathBuf> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut paths = PathsBuilder::new(images, PathBuf::new(), PathBuf::new()).build();
+        paths.set_index(2);
+        paths.toggle_mark(3);
+        paths.retain(|path| path != &PathBuf::from("a"));
+        assert_eq!(paths.current_image_path(), Some(&PathBuf::from("c")));
+        assert_eq!(paths.max_viewable(), Some(4));
+
+        paths.retain(|path| path != &PathBuf::from("c") && path != &PathBuf::from("d"));
+        assert_eq!(paths.current_image_path(), Some(&PathBuf::from("e")));
+        assert!(paths.marked_images().is_empty());
+
+        paths.retain(|path| path == &PathBuf::from("b"));
+        assert_eq!(paths.current_image_path(), Some(&PathBuf::from("b")));
+
+        paths.retain(|_| false);
+        assert_eq!(paths.index(), None);
+        assert_eq!(paths.max_viewable(), None);
+    }
+
+    #[test]
+    fn test_marked_images_are_in_display_order_and_unmarked_on_remove() {
+        let images = vec![
+            PathBuf::from("a.png"),
+            PathBuf::from("b.png"),
+            PathBuf::from("c.png"),
+        ];
+        let mut images = PathsBuilder::new(images, "./keep".into(), ".".into()).build();
+        assert!(images.toggle_mark(2));
+        assert!(images.toggle_mark(0));
+        assert_eq!(
+            images.marked_images(),
+            vec![PathBuf::from("a.png"), PathBuf::from("c.png")]
+        );
+        images.remove_current_image();
+        assert_eq!(images.marked_images(), vec![PathBuf::from("c.png")]);
+        assert!(!images.toggle_mark(1));
+        assert!(images.marked_images().is_empty());
+    }
+
+    #[test]
+    fn test_renaming_image_keeps_position_and_mark() {
+        let images = vec![PathBuf::from("a.png"), PathBuf::from("b.png")];
+        let mut images = PathsBuilder::new(images, "./keep".into(), ".".into()).build();
+        images.set_index(1);
+        images.toggle_mark(1);
+        assert!(images.rename_image(&PathBuf::from("b.png"), PathBuf::from("c.png")));
+        assert_eq!(images.current_image_path(), Some(&PathBuf::from("c.png")));
+        assert_eq!(images.marked_images(), vec![PathBuf::from("c.png")]);
+        assert!(!images.rename_image(&PathBuf::from("b.png"), PathBuf::from("d.png")));
+    }
+
     #[test]
     fn test_removing_and_replacing_images_updates_artificial_len_to_higher_user_set() {
         let mut images = dummy_paths_builder(50).with_maximum_viewable(1).build();
@@ -0,0 +1,73 @@
+//! File that contains functionality that bookmarks images by letter to jump back to, and folders
+//! by name to use as `@name` in `:ng` and `:df`
+use super::Program;
+use std::path::PathBuf;
+
+impl<'a> Program<'a> {
+    /// Bookmarks the current image under the letter
+    pub(super) fn set_image_bookmark(&mut self, letter: char) -> Result<String, String> {
+        let path = match self.paths.current_image_path() {
+            Some(path) => path.clone(),
+            None => return Err("no image to bookmark".to_string()),
+        };
+        self.bookmarks.set_image(letter, &path)?;
+        Ok(format!("bookmarked {} as {}", path.display(), letter))
+    }
+
+    /// Jumps to the image bookmarked under the letter, if it is one of the images being viewed
+    pub(super) fn jump_to_bookmark(&mut self, letter: char) -> Result<(), String> {
+        let path = self
+            .bookmarks
+            .image(letter)
+            .ok_or_else(|| format!("no image is bookmarked as {}", letter))?;
+        let index = self
+            .paths
+            .images()
+            .iter()
+            .position(|image| image == path)
+            .filter(|index| Some(*index) <= self.paths.max_viewable_index())
+            .ok_or_else(|| format!("{} isn't one of the images being viewed", path.display()))?;
+        self.jump_to_image_index(index)?;
+        self.render_screen(false)
+    }
+
+    /// Runs `:bookmark`, bookmarking the folder as the name, or the folder of the current image
+    /// without a folder
+    /// Without arguments the bookmarked folders are displayed
+    pub(super) fn bookmark_command(&mut self, arguments: &str) -> Result<String, String> {
+        let mut split = arguments.trim().splitn(2, ' ');
+        let name = split.next().unwrap_or("");
+        if name.is_empty() {
+            let folders: Vec<String> = self
+                .bookmarks
+                .folders()
+                .map(|(name, folder)| format!("@{}={}", name, folder.display()))
+                .collect();
+            if folders.is_empty() {
+                return Ok("no folders bookmarked".to_string());
+            }
+            return Ok(folders.join(" "));
+        }
+        let folder = match split.next().map(str::trim) {
+            Some(folder) if !folder.is_empty() => {
+                let folder = shellexpand::full(folder)
+                    .map_err(|e| format!("\"{}\": {}", e.var_name, e.cause))?;
+                PathBuf::from(self.bookmarks.expand(&folder)?)
+            }
+            _ => self
+                .paths
+                .current_image_path()
+                .and_then(|path| crate::archive::containing_file(path).parent())
+                .map(|parent| parent.to_path_buf())
+                .ok_or("no folder to bookmark, give one after the name")?,
+        };
+        // Kept absolute so the bookmark works from any directory
+        let folder = if folder.is_absolute() {
+            folder
+        } else {
+            self.paths.base_dir.join(folder)
+        };
+        self.bookmarks.set_folder(name, &folder)?;
+        Ok(format!("bookmarked {} as @{}", folder.display(), name))
+    }
+}
@@ -1,11 +1,13 @@
 //! File that contains Command mode functionality, command mode is a mode that allows verbose input
 //! from the user to perform tasks or edit stored data in the application during runtime
 use super::Program;
+use crate::exclude::Exclusions;
 use crate::sort::SortOrder;
+use crate::template::Fields;
 use crate::ui::{process_command_mode, Action, HelpRender, Mode};
 use regex::Regex;
 use shellexpand::full;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
@@ -24,7 +26,7 @@ enum Commands {
     Sort,
     /// `:ng` or `:newglob`
     ///
-    /// Requires only one addition parameter, the new current_dir.
+    /// Requires at least one additional parameter, the new paths or globs separated by spaces.
     /// If the current image exists prior to changing globs exists in the new glob move to that index.
     /// If the new path has no images do nothing.
     NewGlob,
@@ -48,6 +50,78 @@ enum Commands {
     ///
     /// Sets the maximum number of images to display at any given time
     MaximumImages,
+    /// `:!<command>`
+    ///
+    /// Runs the shell command, `%f` is replaced with the current image, `%F` with the marked
+    /// images and `%d` with the destination folder
+    Exec,
+    /// `:bind`
+    ///
+    /// Requires two arguments, a single character key and the shell command to run when it is
+    /// pressed in normal mode
+    Bind,
+    /// `:unmark`
+    ///
+    /// Unmarks all marked images
+    Unmark,
+    /// `:exclude`
+    ///
+    /// No argument: displays the current exclude patterns.
+    /// One argument: the glob of paths to skip, images matching it are removed from the current
+    /// images and skipped by later `:ng` calls
+    Exclude,
+    /// `:set`
+    ///
+    /// No argument: displays the current settings.
+    /// Otherwise each argument is a `name=value` pair changing a setting, `fit` sets how images
+    /// are fit to the window, `keepview` whether the fit mode and zoom are kept when moving to
+    /// another image, `backup` whether images are backed up before being changed, `export`
+    /// the template exported images are named with, `conflict` what to do when moving or copying
+    /// to a taken name, `keepstructure` whether moved and copied images keep their directories
+    /// and `confirmdelete`, `confirmtrash` and `confirmmove` the number of images deleting,
+    /// trashing or moving them asks to be confirmed at
+    Set,
+    /// `:write-orientation`
+    ///
+    /// Writes the current rotation and flips to the image file
+    WriteOrientation,
+    /// `:export`
+    ///
+    /// Requires the format to convert to, optionally followed by the longest side in pixels to
+    /// shrink images to and the JPEG quality. Writes copies of the current image and (n-1) next
+    /// images when a count was typed, otherwise of the marked images or the current image, to the
+    /// destination folder
+    Export,
+    /// `:rename`
+    ///
+    /// Requires a filename template. Previews the new names of the current image and (n-1) next
+    /// images when a count was typed, otherwise of the marked images or the current image, and
+    /// renames them once confirmed
+    Rename,
+    /// `:trash`
+    ///
+    /// Views the images riv trashed in place of the current images, or every image in the trash
+    /// given `all`. When the trash is being viewed, goes back to the images viewed before
+    Trash,
+    /// `:restore`
+    ///
+    /// Restores the current image and (n-1) next images when a count was typed, otherwise the
+    /// marked images or the current image, from the trash to where they were trashed from
+    Restore,
+    /// `:session`
+    ///
+    /// Requires `save` or `load`, optionally followed by a name. Saves the paths searched, the
+    /// sorting, the maximum, the destination folder, the marks and the current image under the
+    /// name, or views the images saved under it again. Without a name the session riv was
+    /// started with is used, or `default`
+    Session,
+    /// `:bookmark`
+    ///
+    /// No argument: displays the bookmarked folders.
+    /// One argument: bookmarks the folder of the current image as the name.
+    /// Two arguments: bookmarks the folder as the name. Bookmarked folders are used as `@name` in
+    /// `:ng` and `:df`
+    Bookmark,
 }
 
 impl FromStr for Commands {
@@ -63,6 +137,17 @@ impl FromStr for Commands {
             "r" | "reverse" => Ok(Commands::Reverse),
             "df" | "destfolder" => Ok(Commands::DestFolder),
             "m" | "max" => Ok(Commands::MaximumImages),
+            "bind" => Ok(Commands::Bind),
+            "unmark" => Ok(Commands::Unmark),
+            "exclude" => Ok(Commands::Exclude),
+            "set" => Ok(Commands::Set),
+            "write-orientation" => Ok(Commands::WriteOrientation),
+            "export" => Ok(Commands::Export),
+            "rename" => Ok(Commands::Rename),
+            "trash" => Ok(Commands::Trash),
+            "restore" => Ok(Commands::Restore),
+            "session" => Ok(Commands::Session),
+            "bookmark" => Ok(Commands::Bookmark),
             _ => Err(format!(
                 "No such command \"{}\", type :? for command help",
                 s
@@ -71,33 +156,46 @@ impl FromStr for Commands {
     }
 }
 
-/// Globs the passed path, returning an error if no images are in that path, glob::glob fails, or
-/// path is unexpected
-fn glob_path(path: &PathBuf) -> Result<Vec<PathBuf>, String> {
-    use crate::cli::push_image_path;
-
-    let mut new_images: Vec<PathBuf> = Vec::new();
-    let path_matches = glob::glob(&path.to_string_lossy()).map_err(|e| e.to_string())?;
-    for path in path_matches {
-        match path {
-            Ok(p) => {
-                push_image_path(&mut new_images, p);
-            }
-            Err(e) => {
-                let err_msg = format!("Unexpected path {}", e);
-                return Err(err_msg);
-            }
-        }
-    }
+/// Globs the passed paths, returning an error if no images are in those paths, glob::glob fails,
+/// or a path is unexpected
+/// Returns the images found and their new base directory
+pub(super) fn glob_paths(
+    base_dir: &PathBuf,
+    paths: &[String],
+    exclusions: &Exclusions,
+) -> Result<(Vec<PathBuf>, PathBuf), String> {
+    let (new_images, new_base_dir) = crate::cli::discover_images(base_dir, paths, exclusions)?;
     if new_images.is_empty() {
-        let err_msg = format!("Path \"{}\" had no images", path.display());
+        let err_msg = format!("Path \"{}\" had no images", paths.join(" "));
         return Err(err_msg);
     }
-    Ok(new_images)
+    Ok((new_images, new_base_dir))
+}
+
+/// Parses the value of an on or off setting
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(format!("\"{}\" is not on or off", value)),
+    }
+}
+
+/// Details of a made up image, templates are checked by naming it before they are used
+fn example_fields() -> Fields<'static> {
+    Fields {
+        path: Path::new("image.png"),
+        n: 1,
+        ext: "png",
+    }
 }
 
 /// Separate user input into the main command and its respected arguments
 fn parse_user_input(input: String) -> Result<(Commands, String), String> {
+    // Shell commands don't need a space after the `!`
+    if let Some(command) = input.strip_prefix('!') {
+        return Ok((Commands::Exec, command.trim().to_owned()));
+    }
     // find where to split
     let command_terminating_index = {
         if let Some(space_index) = input.find(' ') {
@@ -154,23 +252,34 @@ impl<'a> Program<'a> {
         Ok(input)
     }
 
-    /// Takes a path to a directory or glob and adds these images to self.paths.images
-    fn newglob(&mut self, path_to_newglob: &str) {
-        let path = match crate::path_to_glob(&self.paths.base_dir, path_to_newglob) {
-            Ok(path) => path,
-            Err(e) => {
-                self.ui_state.mode = Mode::Error(e.to_string());
-                return;
-            }
-        };
-        let msg = path_to_newglob.to_owned();
-        let new_images = match glob_path(&path) {
-            Ok(new_images) => new_images,
+    /// Takes paths to directories or globs, separated by spaces, and replaces self.paths.images
+    /// with their images
+    fn newglob(&mut self, paths_to_newglob: &str) {
+        if let Err(e) = self.reject_while_transferring() {
+            self.ui_state.mode = Mode::Error(e);
+            return;
+        }
+        // Bookmarked folders are swapped in for `@name`
+        let globs: Result<Vec<String>, String> = crate::split_paths(paths_to_newglob)
+            .iter()
+            .map(|path| self.bookmarks.expand(path))
+            .collect();
+        let globs = match globs {
+            Ok(globs) => globs,
             Err(e) => {
-                self.ui_state.mode = Mode::Error(e.to_string());
+                self.ui_state.mode = Mode::Error(e);
                 return;
             }
         };
+        let msg = paths_to_newglob.to_owned();
+        let (new_images, new_base_dir) =
+            match glob_paths(&self.paths.base_dir, &globs, &self.exclusions) {
+                Ok(found) => found,
+                Err(e) => {
+                    self.ui_state.mode = Mode::Error(e.to_string());
+                    return;
+                }
+            };
         let target = match self.paths.current_image_path() {
             Some(path) => {
                 // Clone the path because the whole image set is going to be swapped out
@@ -181,12 +290,14 @@ impl<'a> Program<'a> {
         };
 
         self.paths.reload_images(new_images);
+        // The new images replace the trash, rather than being replaced when it is closed
+        self.trash_view = None;
+        // Remembered for saving the session
+        self.search_dir = self.paths.base_dir.clone();
+        self.globs = globs;
 
         // Set current directory to new one
-        let new_base_dir = crate::new_base_dir(&path);
-        if let Ok(base_dir) = new_base_dir {
-            self.paths.base_dir = base_dir
-        }
+        self.paths.base_dir = new_base_dir;
         self.sorter.sort(self.paths.images_as_mut_slice());
         if let Some(target_path) = target {
             if let Some(new_index) = self
@@ -273,6 +384,123 @@ impl<'a> Program<'a> {
         self.paths.set_actual_maximum(new_actual_max);
     }
 
+    /// Binds a single character key to a shell command, arguments are the key and the command
+    fn bind(&mut self, arguments: &str) {
+        let mut split = arguments.splitn(2, ' ');
+        let key = split.next().unwrap_or("");
+        let command = split.next().unwrap_or("").trim();
+        if key.chars().count() != 1 || command.is_empty() {
+            self.ui_state.mode = Mode::Error(
+                "Command \":bind\" requires a single character key and a command".to_string(),
+            );
+            return;
+        }
+        if let Err(e) = crate::cli::check_binding_key(key) {
+            self.ui_state.mode = Mode::Error(e);
+            return;
+        }
+        self.ui_state
+            .bindings
+            .insert(key.to_string(), command.to_string());
+        self.ui_state.mode = Mode::Success(format!("bound \"{}\" to {}", key, command));
+        self.ui_state.rerender_time = Some(Instant::now());
+    }
+
+    /// Adds a pattern of paths to skip and stops tracking the current images it matches
+    /// Without a pattern the current patterns are displayed
+    fn exclude(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            let patterns = self.exclusions.patterns();
+            let msg = if patterns.is_empty() {
+                "no exclude patterns set".to_string()
+            } else {
+                format!("excluding {}", patterns.join(" "))
+            };
+            self.ui_state.mode = Mode::Success(msg);
+            self.ui_state.rerender_time = Some(Instant::now());
+            return;
+        }
+        if let Err(e) = self.exclusions.add(pattern) {
+            self.ui_state.mode = Mode::Error(e);
+            return;
+        }
+        let before = self.paths.images().len();
+        let exclusions = &self.exclusions;
+        let base_dir = self.paths.base_dir.clone();
+        self.paths
+            .retain(|path| !exclusions.is_excluded(path, &base_dir));
+        let removed = before - self.paths.images().len();
+        if removed > 0 {
+            self.screen.dirty = true;
+        }
+        self.ui_state.mode = Mode::Success(format!("excluded {} images with {}", removed, pattern));
+        self.ui_state.rerender_time = Some(Instant::now());
+    }
+
+    /// Changes the settings given as `name=value` pairs separated by spaces
+    /// Without arguments the current settings are displayed
+    fn set(&mut self, arguments: &str) {
+        let mut changed = Vec::new();
+        for setting in arguments.split_whitespace() {
+            let mut split = setting.splitn(2, '=');
+            let name = split.next().unwrap_or("");
+            let value = match split.next() {
+                Some(value) => value,
+                None => {
+                    self.ui_state.mode =
+                        Mode::Error(format!("Expected name=value, found \"{}\"", setting));
+                    return;
+                }
+            };
+            let result = match name {
+                "fit" => value.parse().map(|mode| {
+                    self.ui_state.default_fit = mode;
+                    self.ui_state.fit_mode = Some(mode);
+                }),
+                "keepview" => parse_bool(value).map(|keep| self.ui_state.keep_view = keep),
+                "backup" => parse_bool(value).map(|backup| self.backup = backup),
+                "export" => crate::template::expand(value, &example_fields())
+                    .map(|_| self.export_template = value.to_string()),
+                "keepstructure" => parse_bool(value).map(|keep| self.keep_structure = keep),
+                "confirmdelete" => self.confirm.set("delete", value),
+                "confirmtrash" => self.confirm.set("trash", value),
+                "confirmmove" => self.confirm.set("move", value),
+                "conflict" => value.parse().map(|policy| self.conflict_policy = policy),
+                _ => Err(format!("No such setting \"{}\"", name)),
+            };
+            if let Err(e) = result {
+                self.ui_state.mode = Mode::Error(e);
+                return;
+            }
+            changed.push(setting);
+        }
+        let msg = if changed.is_empty() {
+            format!(
+                "fit={} keepview={} backup={} export={} conflict={} keepstructure={} \
+                 confirmdelete={} confirmtrash={} confirmmove={}",
+                self.ui_state.default_fit,
+                self.ui_state.keep_view,
+                self.backup,
+                self.export_template,
+                self.conflict_policy,
+                self.keep_structure,
+                self.confirm.delete,
+                self.confirm.trash,
+                self.confirm.move_images
+            )
+        } else {
+            changed.join(" ")
+        };
+        self.ui_state.mode = Mode::Success(msg);
+        self.ui_state.rerender_time = Some(Instant::now());
+        // Applies a new fit mode to the current image
+        if changed.iter().any(|setting| setting.starts_with("fit=")) {
+            self.ui_state.scale = self.calculate_scale_for_fit();
+            self.ui_state.pan_x = 0.0;
+            self.ui_state.pan_y = 0.0;
+        }
+    }
+
     /// Enters command mode that gets user input and runs a set of possible commands based on user input.
     /// After every command the user is set either into normal mode or the app terminates.
     ///
@@ -283,8 +511,18 @@ impl<'a> Program<'a> {
         self.ui_state.render_infobar = true;
         self.render_screen(false)?;
         let input = self.get_command(":")?;
+        self.execute_command(input)
+    }
+
+    /// Runs a single command mode command, the input is the text typed after the `:`
+    /// The result of the command is reported through `self.ui_state.mode`
+    ///
+    /// Error is returned only in serious cases, for instance if the application fails to render_screen
+    pub(super) fn execute_command(&mut self, input: String) -> Result<(), String> {
         // after evaluating a command always exit to normal mode by default
         self.ui_state.mode = Mode::Normal;
+        // The count only applies to this command
+        let count = std::mem::replace(&mut self.ui_state.command_count, 1);
         // Empty input means switch back to normal mode
         if input.is_empty() {
             return Ok(());
@@ -299,8 +537,9 @@ impl<'a> Program<'a> {
         match command {
             Commands::NewGlob => {
                 if arguments.is_empty() {
-                    self.ui_state.mode =
-                        Mode::Error(("Command \"newglob\" or \":ng\" requires a glob").to_string());
+                    self.ui_state.mode = Mode::Error(
+                        ("Command \"newglob\" or \":ng\" requires at least one glob").to_string(),
+                    );
                     return Ok(());
                 }
                 self.newglob(&arguments);
@@ -314,6 +553,8 @@ impl<'a> Program<'a> {
             }
             Commands::Reverse => {
                 self.paths.reverse();
+                // Later sorts keep the reversed order
+                self.sorter.set_reverse(!self.sorter.reversed());
             }
             Commands::DestFolder => {
                 if arguments.is_empty() {
@@ -337,6 +578,13 @@ impl<'a> Program<'a> {
                                 .replace_all(&path, "$1")
                                 .to_string();
                         }
+                        let path = match self.bookmarks.expand(&path) {
+                            Ok(path) => path,
+                            Err(e) => {
+                                self.ui_state.mode = Mode::Error(e);
+                                return Ok(());
+                            }
+                        };
                         let success_msg =
                             format!("destination folder successfully set to {}", path);
                         self.paths.dest_folder = PathBuf::from(path);
@@ -362,6 +610,67 @@ impl<'a> Program<'a> {
             Commands::Sort => {
                 self.sort(arguments);
             }
+            Commands::Exec => {
+                if arguments.is_empty() {
+                    self.ui_state.mode =
+                        Mode::Error("Command \":!\" requires a shell command".to_string());
+                    return Ok(());
+                }
+                match self.run_external(&arguments) {
+                    Ok(s) => {
+                        self.ui_state.mode = Mode::Success(s);
+                        self.ui_state.rerender_time = Some(Instant::now());
+                    }
+                    Err(e) => self.ui_state.mode = Mode::Error(e),
+                }
+            }
+            Commands::Bind => self.bind(&arguments),
+            Commands::Unmark => self.paths.clear_marks(),
+            Commands::Exclude => self.exclude(arguments.trim()),
+            Commands::Set => self.set(&arguments),
+            Commands::WriteOrientation => match self.write_orientation() {
+                Ok(msg) => {
+                    self.ui_state.mode = Mode::Success(msg);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Err(e) => self.ui_state.mode = Mode::Error(e),
+            },
+            Commands::Rename => self.start_rename(arguments.trim(), count),
+            Commands::Export => match self.export_images(&arguments, count) {
+                Ok(msg) => {
+                    self.ui_state.mode = Mode::Success(msg);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Err(e) => self.ui_state.mode = Mode::Error(e),
+            },
+            Commands::Trash => match self.toggle_trash(arguments.trim()) {
+                Ok(msg) => {
+                    self.ui_state.mode = Mode::Success(msg);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Err(e) => self.ui_state.mode = Mode::Error(e),
+            },
+            Commands::Restore => match self.restore_images(count) {
+                Ok(msg) => {
+                    self.ui_state.mode = Mode::Success(msg);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Err(e) => self.ui_state.mode = Mode::Error(e),
+            },
+            Commands::Session => match self.session_command(&arguments) {
+                Ok(msg) => {
+                    self.ui_state.mode = Mode::Success(msg);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Err(e) => self.ui_state.mode = Mode::Error(e),
+            },
+            Commands::Bookmark => match self.bookmark_command(&arguments) {
+                Ok(msg) => {
+                    self.ui_state.mode = Mode::Success(msg);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Err(e) => self.ui_state.mode = Mode::Error(e),
+            },
         }
         Ok(())
     }
@@ -0,0 +1,168 @@
+//! File that contains A/B comparison functionality, an image is pinned on the left while the
+//! current image is shown on the right with the same zoom and pan applied to both
+use super::render::load_image_texture;
+use super::Program;
+use crate::ui::{Compare, Mode};
+use sdl2::rect::Rect;
+use std::time::{Duration, Instant};
+
+/// How long each image is shown for when flickering between the pinned and current images
+const FLICKER_INTERVAL: Duration = Duration::from_millis(500);
+
+impl<'a> Program<'a> {
+    /// Pins the current image to compare the following images against
+    /// Stops comparing if an image is already pinned
+    pub(super) fn toggle_compare(&mut self) -> Result<(), String> {
+        if self.ui_state.compare.take().is_some() {
+            self.screen.pinned_texture = None;
+            self.ui_state.scale = self.calculate_scale_for_fit();
+            return self.render_screen(false);
+        }
+        let pinned = match self.paths.current_image_path() {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+        let texture = match load_image_texture(self.screen.texture_creator, &pinned) {
+            Ok(texture) => texture,
+            Err(e) => {
+                self.ui_state.mode = Mode::Error(format!("Failed to pin image: {}", e));
+                return Ok(());
+            }
+        };
+        // Only one image can be shown next to the current image
+        self.ui_state.spread = false;
+        self.screen.second_texture = None;
+        self.screen.pinned_texture = Some(texture);
+        self.screen.pinned_orientation = self.screen.last_orientation;
+        self.ui_state.compare = Some(Compare {
+            pinned,
+            flicker: None,
+            showing_pinned: false,
+        });
+        self.ui_state.scale = self.calculate_scale_for_fit();
+        self.ui_state.mode =
+            Mode::Success("pinned image, move to the images to compare against it".to_string());
+        self.ui_state.rerender_time = Some(Instant::now());
+        self.render_screen(false)
+    }
+
+    /// Pins the current image in place of the pinned image, and moves to the image that was pinned
+    /// The zoom and pan are kept so the same area of both images stays in view
+    pub(super) fn swap_compare(&mut self) -> Result<(), String> {
+        let pinned = match self.ui_state.compare.as_ref() {
+            Some(compare) => compare.pinned.clone(),
+            None => {
+                self.ui_state.mode =
+                    Mode::Error("No image is pinned, press a to pin one".to_string());
+                return Ok(());
+            }
+        };
+        let (current, max_index) = match (
+            self.paths.current_image_path(),
+            self.paths.max_viewable_index(),
+        ) {
+            (Some(path), Some(max_index)) => (path.clone(), max_index),
+            _ => return Ok(()),
+        };
+        let pinned_index = match self.paths.images().iter().position(|path| path == &pinned) {
+            Some(index) if index <= max_index => index,
+            _ => {
+                self.ui_state.mode =
+                    Mode::Error("The pinned image is no longer in the image list".to_string());
+                return Ok(());
+            }
+        };
+
+        // Both images are already loaded, so swap them instead of loading them again
+        std::mem::swap(
+            &mut self.screen.last_texture,
+            &mut self.screen.pinned_texture,
+        );
+        std::mem::swap(
+            &mut self.screen.last_orientation,
+            &mut self.screen.pinned_orientation,
+        );
+        self.paths.set_index(pinned_index);
+        self.screen.last_index = Some(pinned_index);
+        if let Some(compare) = self.ui_state.compare.as_mut() {
+            compare.pinned = current;
+            compare.showing_pinned = !compare.showing_pinned;
+        }
+        self.render_screen(false)
+    }
+
+    /// Toggles alternating the pinned and current images in the same place rather than side by side
+    pub(super) fn toggle_flicker(&mut self) -> Result<(), String> {
+        let compare = match self.ui_state.compare.as_mut() {
+            Some(compare) => compare,
+            None => {
+                self.ui_state.mode =
+                    Mode::Error("No image is pinned, press a to pin one".to_string());
+                return Ok(());
+            }
+        };
+        compare.flicker = match compare.flicker {
+            Some(_) => None,
+            None => Some(Instant::now()),
+        };
+        compare.showing_pinned = false;
+        // The images have the whole window to themselves when flickering
+        self.ui_state.scale = self.calculate_scale_for_fit();
+        self.render_screen(false)
+    }
+
+    /// Switches the flickering image once it has been shown for long enough
+    pub(super) fn update_flicker(&mut self) -> Result<(), String> {
+        let compare = match self.ui_state.compare.as_mut() {
+            Some(compare) => compare,
+            None => return Ok(()),
+        };
+        match compare.flicker {
+            Some(switched) if switched.elapsed() >= FLICKER_INTERVAL => {
+                compare.flicker = Some(Instant::now());
+                compare.showing_pinned = !compare.showing_pinned;
+                self.render_screen(false)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Area images are fit and panned within
+    /// When comparing side by side each image has half of the window
+    pub(super) fn image_area(&self) -> Rect {
+        let viewport = self.screen.canvas.viewport();
+        match self.ui_state.compare {
+            Some(Compare { flicker: None, .. }) => compare_panes(viewport).1,
+            _ => viewport,
+        }
+    }
+}
+
+/// Splits the window into the left pane for the pinned image and the right pane for the current
+/// image
+pub(super) fn compare_panes(viewport: Rect) -> (Rect, Rect) {
+    let left_width = viewport.width() / 2;
+    let right_width = viewport.width() - left_width;
+    (
+        Rect::new(viewport.x(), viewport.y(), left_width, viewport.height()),
+        Rect::new(
+            viewport.x() + left_width as i32,
+            viewport.y(),
+            right_width,
+            viewport.height(),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare_panes;
+    use sdl2::rect::Rect;
+
+    #[test]
+    fn test_compare_panes_split_window_in_half() {
+        let (left, right) = compare_panes(Rect::new(0, 0, 801, 600));
+        assert_eq!(left, Rect::new(0, 0, 400, 600));
+        assert_eq!(right, Rect::new(400, 0, 401, 600));
+    }
+}
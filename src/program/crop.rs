@@ -0,0 +1,527 @@
+//! File that contains crop mode functionality, part of the current image is selected by dragging
+//! with the mouse or with the keyboard and saved as a new image or over the original
+use super::edit::{open_image, write_image, JPEG_QUALITY};
+use super::render::{place, texture_dst, upright_query};
+use super::Program;
+use crate::conflict::free_path;
+use crate::orientation::Orientation;
+use crate::ui::{self, Crop, CropAction, Mode, PanAction};
+use image::ImageFormat;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Pixels of the window the selection moves or grows by for each key press
+const STEP: i32 = 10;
+/// Aspect ratios the selection can be locked to, cycled through in order after a free selection
+const ASPECTS: [(u32, u32); 7] = [(1, 1), (4, 3), (3, 4), (3, 2), (2, 3), (16, 9), (9, 16)];
+
+impl<'a> Program<'a> {
+    /// Switches to crop mode with the whole image selected
+    pub(super) fn start_crop(&mut self) {
+        if self.screen.second_texture.is_some() || self.ui_state.compare.is_some() {
+            self.ui_state.mode =
+                Mode::Error("Stop comparing or reading spreads to crop an image".to_string());
+            return;
+        }
+        let query = match self.screen.last_texture.as_ref() {
+            Some(texture) => texture.query(),
+            None => return,
+        };
+        self.ui_state.crop = Some(Crop {
+            selection: Rect::new(0, 0, query.width, query.height),
+            anchor: None,
+            aspect: None,
+            confirm_overwrite: false,
+            sideways: self.view_orientation().rotation.is_sideways(),
+        });
+        self.ui_state.mode = Mode::Crop;
+    }
+
+    /// Crop mode listens for input selecting part of the image until it is saved or cancelled
+    pub(super) fn run_crop_mode(&mut self) -> Result<(), String> {
+        self.render_screen(false)?;
+        self.run_modal(|program, event| {
+            let action = ui::process_crop_mode(event);
+            if action != CropAction::Noop {
+                program.crop_action(action)?;
+            }
+            if program.ui_state.mode == Mode::Crop {
+                return Ok(None);
+            }
+            Ok(Some(()))
+        })?;
+        self.ui_state.crop = None;
+        Ok(())
+    }
+
+    fn crop_action(&mut self, action: CropAction) -> Result<(), String> {
+        match action {
+            CropAction::Save => return self.finish_crop(false),
+            CropAction::Overwrite if matches!(&self.ui_state.crop, Some(crop) if crop.confirm_overwrite) => {
+                return self.finish_crop(true)
+            }
+            CropAction::Cancel => {
+                self.ui_state.mode = Mode::Normal;
+                return Ok(());
+            }
+            CropAction::Quit => {
+                self.ui_state.mode = Mode::Exit;
+                return Ok(());
+            }
+            _ => {}
+        }
+        let (dst, size, view) = match self.crop_view() {
+            Some(view) => view,
+            None => return Ok(()),
+        };
+        let crop = match self.ui_state.crop.as_mut() {
+            Some(crop) => crop,
+            None => return Ok(()),
+        };
+        // Any other input cancels overwriting
+        crop.confirm_overwrite = false;
+        let shown = image_rect_to_screen(crop.selection, dst, size, view);
+        let to_image = |rect: Rect| screen_rect_to_image(rect, dst, size, view);
+        match action {
+            CropAction::Start(x, y) => crop.anchor = Some((x, y)),
+            CropAction::Drag(x, y) => {
+                if let Some(anchor) = crop.anchor {
+                    let rect = selection_between(anchor, (x, y), crop.aspect);
+                    if let Some(selection) = clip(to_image(rect), size) {
+                        crop.selection = selection;
+                    }
+                }
+            }
+            CropAction::Release => crop.anchor = None,
+            CropAction::Move(direction) => {
+                let (dx, dy) = offset(&direction);
+                let mut moved = shown;
+                moved.offset(dx, dy);
+                crop.selection = shift_inside(to_image(moved), size);
+            }
+            CropAction::Resize(direction) => {
+                let (dx, dy) = offset(&direction);
+                let mut width = (shown.width() as i32 + dx).max(1);
+                let mut height = (shown.height() as i32 + dy).max(1);
+                if let Some(ratio) = crop.aspect {
+                    let (w, h) = with_aspect(width, height, ratio, dx != 0);
+                    width = w;
+                    height = h;
+                }
+                let rect = Rect::new(shown.x(), shown.y(), width as u32, height as u32);
+                if let Some(selection) = clip(to_image(rect), size) {
+                    crop.selection = selection;
+                }
+            }
+            CropAction::CycleAspect => {
+                crop.aspect = next_aspect(crop.aspect);
+                if let Some(ratio) = crop.aspect {
+                    let (width, height) = (shown.width() as i32, shown.height() as i32);
+                    let keep_width = width * ratio.1 as i32 <= height * ratio.0 as i32;
+                    let (width, height) = with_aspect(width, height, ratio, keep_width);
+                    let rect = Rect::new(shown.x(), shown.y(), width as u32, height as u32);
+                    if let Some(selection) = clip(to_image(rect), size) {
+                        crop.selection = selection;
+                    }
+                }
+            }
+            CropAction::Overwrite => crop.confirm_overwrite = true,
+            _ => {}
+        }
+        self.render_screen(false)
+    }
+
+    /// Saves the selection and leaves crop mode, reporting how it went
+    fn finish_crop(&mut self, overwrite: bool) -> Result<(), String> {
+        match self.save_crop(overwrite) {
+            Ok(msg) => {
+                self.ui_state.mode = Mode::Success(msg);
+                self.ui_state.rerender_time = Some(Instant::now());
+            }
+            Err(e) => self.ui_state.mode = Mode::Error(format!("Failed to crop image: {}", e)),
+        }
+        Ok(())
+    }
+
+    /// Writes the selected part of the image, turned the way it is being viewed, over the image
+    /// or to a new image in the destination folder
+    fn save_crop(&mut self, overwrite: bool) -> Result<String, String> {
+        self.reject_dry_run("saving crops")?;
+        self.reject_while_transferring()?;
+        let (path, selection) = match (self.paths.current_image_path(), &self.ui_state.crop) {
+            (Some(path), Some(crop)) => (path.clone(), crop.selection),
+            _ => return Err("nothing is selected".to_string()),
+        };
+        if overwrite && crate::archive::is_entry(&path) {
+            return Err("images inside archives can't be changed".to_string());
+        }
+        if overwrite {
+            self.reject_in_trash("changed")?;
+        }
+        let view = self.view_orientation();
+        let image = open_image(&path)?;
+        let cropped = view.apply(image.crop_imm(
+            selection.x() as u32,
+            selection.y() as u32,
+            selection.width(),
+            selection.height(),
+        ));
+
+        if overwrite {
+            let format = ImageFormat::from_path(&path).map_err(|e| e.to_string())?;
+            if !format.can_write() {
+                return Err(format!("{:?} images can't be written", format));
+            }
+            self.replace_file("crop", &path, |tmp| {
+                write_image(&cropped, tmp, format, JPEG_QUALITY)
+            })?;
+            self.screen.dirty = true;
+            return Ok(format!("cropped {}", path.display()));
+        }
+        fs::create_dir_all(&self.paths.dest_folder).map_err(|e| e.to_string())?;
+        let (dest, format) = crop_destination(&self.paths.dest_folder, &path);
+        if let Err(e) = write_image(&cropped, &dest, format, JPEG_QUALITY) {
+            self.journal.failed("crop", &path, Some(&dest), &e);
+            return Err(e);
+        }
+        self.journal.ok("crop", &path, Some(&dest));
+        Ok(format!("saved crop to {}", dest.display()))
+    }
+
+    /// Where the current image is drawn, its size and how it is turned
+    fn crop_view(&self) -> Option<(Rect, (u32, u32), Orientation)> {
+        let texture = self.screen.last_texture.as_ref()?;
+        let orientation = self.screen.last_orientation;
+        let dst = place(
+            &upright_query(texture, orientation),
+            self.image_area(),
+            self.ui_state.scale,
+            self.ui_state.pan_x,
+            self.ui_state.pan_y,
+            &self.ui_state.rot_angle,
+        );
+        let query = texture.query();
+        Some((
+            texture_dst(dst, orientation),
+            (query.width, query.height),
+            self.view_orientation(),
+        ))
+    }
+
+    /// Outlines the selection and dims the rest of the window
+    pub(super) fn render_crop_selection(&mut self) -> Result<(), String> {
+        let selection = match &self.ui_state.crop {
+            Some(crop) => crop.selection,
+            None => return Ok(()),
+        };
+        let (dst, size, view) = match self.crop_view() {
+            Some(view) => view,
+            None => return Ok(()),
+        };
+        let shown = image_rect_to_screen(selection, dst, size, view);
+        let viewport = self.screen.canvas.viewport();
+        let (width, height) = (viewport.width() as i32, viewport.height() as i32);
+        let outside: Vec<Rect> = [
+            (0, 0, width, shown.top()),
+            (0, shown.bottom(), width, height - shown.bottom()),
+            (0, shown.top(), shown.left(), shown.height() as i32),
+            (
+                shown.right(),
+                shown.top(),
+                width - shown.right(),
+                shown.height() as i32,
+            ),
+        ]
+        .iter()
+        .filter(|(_, _, w, h)| *w > 0 && *h > 0)
+        .map(|&(x, y, w, h)| Rect::new(x, y, w as u32, h as u32))
+        .collect();
+        self.screen.canvas.set_blend_mode(BlendMode::Blend);
+        self.screen.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        self.screen.canvas.fill_rects(&outside)?;
+        self.screen.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        self.screen.canvas.draw_rect(shown)
+    }
+}
+
+/// Distance to move in the window for a key press in the direction
+fn offset(direction: &PanAction) -> (i32, i32) {
+    match direction {
+        PanAction::Left => (-STEP, 0),
+        PanAction::Right => (STEP, 0),
+        PanAction::Up => (0, -STEP),
+        PanAction::Down => (0, STEP),
+    }
+}
+
+/// The aspect ratio after `aspect` when cycling through them, a free selection follows the last
+fn next_aspect(aspect: Option<(u32, u32)>) -> Option<(u32, u32)> {
+    match aspect {
+        None => Some(ASPECTS[0]),
+        Some(ratio) => ASPECTS
+            .iter()
+            .position(|&r| r == ratio)
+            .and_then(|i| ASPECTS.get(i + 1))
+            .copied(),
+    }
+}
+
+/// Turns a point around the origin by quarter turns clockwise, screen coordinates point down
+fn turn((x, y): (f32, f32), turns: u8) -> (f32, f32) {
+    match turns % 4 {
+        0 => (x, y),
+        1 => (-y, x),
+        2 => (-x, -y),
+        _ => (y, -x),
+    }
+}
+
+/// Maps a point in the window to pixels of an image of `size` drawn unrotated at `dst` and then
+/// turned about the centre of `dst` by `view`, as `make_dst` and the renderer place images
+pub(super) fn screen_to_image(
+    point: (f32, f32),
+    dst: Rect,
+    size: (u32, u32),
+    view: Orientation,
+) -> (f32, f32) {
+    let scale = dst.width() as f32 / size.0 as f32;
+    let centre = (
+        dst.x() as f32 + dst.width() as f32 / 2.0,
+        dst.y() as f32 + dst.height() as f32 / 2.0,
+    );
+    let from_centre = ((point.0 - centre.0) / scale, (point.1 - centre.1) / scale);
+    let (x, y) = turn(from_centre, 4 - view.quarter_turns());
+    let x = if view.mirrored { -x } else { x };
+    (x + size.0 as f32 / 2.0, y + size.1 as f32 / 2.0)
+}
+
+/// Maps pixels of the image to the point in the window they are drawn at, the inverse of
+/// `screen_to_image`
+pub(super) fn image_to_screen(
+    point: (f32, f32),
+    dst: Rect,
+    size: (u32, u32),
+    view: Orientation,
+) -> (f32, f32) {
+    let scale = dst.width() as f32 / size.0 as f32;
+    let centre = (
+        dst.x() as f32 + dst.width() as f32 / 2.0,
+        dst.y() as f32 + dst.height() as f32 / 2.0,
+    );
+    let x = point.0 - size.0 as f32 / 2.0;
+    let x = if view.mirrored { -x } else { x };
+    let (x, y) = turn((x, point.1 - size.1 as f32 / 2.0), view.quarter_turns());
+    (centre.0 + x * scale, centre.1 + y * scale)
+}
+
+/// The rectangle with opposite corners at the two points
+fn bounding_rect(a: (f32, f32), b: (f32, f32)) -> Rect {
+    let (left, right) = (a.0.min(b.0).round(), a.0.max(b.0).round());
+    let (top, bottom) = (a.1.min(b.1).round(), a.1.max(b.1).round());
+    Rect::new(
+        left as i32,
+        top as i32,
+        (right - left).max(1.0) as u32,
+        (bottom - top).max(1.0) as u32,
+    )
+}
+
+/// Area of the image under a rectangle in the window, which may reach past the image
+pub(super) fn screen_rect_to_image(
+    rect: Rect,
+    dst: Rect,
+    size: (u32, u32),
+    view: Orientation,
+) -> Rect {
+    let corner = |x: i32, y: i32| screen_to_image((x as f32, y as f32), dst, size, view);
+    bounding_rect(
+        corner(rect.left(), rect.top()),
+        corner(rect.right(), rect.bottom()),
+    )
+}
+
+/// Area of the window an area of the image is drawn in
+pub(super) fn image_rect_to_screen(
+    rect: Rect,
+    dst: Rect,
+    size: (u32, u32),
+    view: Orientation,
+) -> Rect {
+    let corner = |x: i32, y: i32| image_to_screen((x as f32, y as f32), dst, size, view);
+    bounding_rect(
+        corner(rect.left(), rect.top()),
+        corner(rect.right(), rect.bottom()),
+    )
+}
+
+/// The part of `rect` within an image of `size`, None if it misses the image
+fn clip(rect: Rect, size: (u32, u32)) -> Option<Rect> {
+    rect.intersection(Rect::new(0, 0, size.0, size.1))
+}
+
+/// Moves `rect` back inside an image of `size`, shrinking it only if it is larger than the image
+fn shift_inside(rect: Rect, size: (u32, u32)) -> Rect {
+    let width = rect.width().min(size.0);
+    let height = rect.height().min(size.1);
+    let x = rect.x().clamp(0, (size.0 - width) as i32);
+    let y = rect.y().clamp(0, (size.1 - height) as i32);
+    Rect::new(x, y, width, height)
+}
+
+/// Width and height with the aspect ratio `ratio`, keeping the width or the height as it is
+fn with_aspect(width: i32, height: i32, ratio: (u32, u32), keep_width: bool) -> (i32, i32) {
+    let (ratio_w, ratio_h) = (ratio.0 as f32, ratio.1 as f32);
+    if keep_width {
+        (
+            width,
+            ((width as f32 * ratio_h / ratio_w).round() as i32).max(1),
+        )
+    } else {
+        (
+            ((height as f32 * ratio_w / ratio_h).round() as i32).max(1),
+            height,
+        )
+    }
+}
+
+/// Selection in the window dragged from `anchor` to `corner`
+/// When an aspect ratio is locked the selection is the largest one with that ratio that fits
+/// within the dragged area, growing away from the anchor
+fn selection_between(anchor: (i32, i32), corner: (i32, i32), aspect: Option<(u32, u32)>) -> Rect {
+    let (dx, dy) = (corner.0 - anchor.0, corner.1 - anchor.1);
+    let (mut width, mut height) = (dx.abs().max(1), dy.abs().max(1));
+    if let Some(ratio) = aspect {
+        let keep_width = width * ratio.1 as i32 <= height * ratio.0 as i32;
+        let (w, h) = with_aspect(width, height, ratio, keep_width);
+        width = w;
+        height = h;
+    }
+    let x = if dx < 0 { anchor.0 - width } else { anchor.0 };
+    let y = if dy < 0 { anchor.1 - height } else { anchor.1 };
+    Rect::new(x, y, width as u32, height as u32)
+}
+
+/// Path in the destination folder to save a crop of the image at `path` to, and the format to
+/// save it in
+/// Crops are named after the image, numbered if a crop of it already exists, and saved as PNGs if
+/// the format of the image can't be written
+fn crop_destination(dest_folder: &Path, path: &Path) -> (PathBuf, ImageFormat) {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let (format, ext) = match ImageFormat::from_path(path) {
+        Ok(format) if format.can_write() => (
+            format,
+            path.extension().unwrap_or_default().to_string_lossy(),
+        ),
+        _ => (ImageFormat::Png, "png".into()),
+    };
+    let dest = free_path(&dest_folder.join(format!("{}_crop.{}", stem, ext)));
+    (dest, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        image_to_screen, screen_rect_to_image, screen_to_image, selection_between, shift_inside,
+    };
+    use crate::orientation::Orientation;
+    use crate::ui::RotAngle;
+    use sdl2::rect::Rect;
+
+    const ERROR: f32 = 0.01;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < ERROR && (a.1 - b.1).abs() < ERROR
+    }
+
+    #[test]
+    fn test_screen_to_image_follows_zoom_and_pan() {
+        // A 200x100 image zoomed to twice its size and panned so it starts at (100, 50)
+        let dst = Rect::new(100, 50, 400, 200);
+        let view = Orientation::default();
+        assert!(close(
+            screen_to_image((100.0, 50.0), dst, (200, 100), view),
+            (0.0, 0.0)
+        ));
+        assert!(close(
+            screen_to_image((500.0, 250.0), dst, (200, 100), view),
+            (200.0, 100.0)
+        ));
+        assert!(close(
+            screen_to_image((300.0, 150.0), dst, (200, 100), view),
+            (100.0, 50.0)
+        ));
+    }
+
+    #[test]
+    fn test_screen_to_image_follows_rotation_and_mirroring() {
+        let dst = Rect::from_center((300, 150), 400, 200);
+        let turned = Orientation {
+            rotation: RotAngle::Right,
+            mirrored: false,
+        };
+        // Turned clockwise the top left of the image is at the top right of what is shown
+        assert!(close(
+            image_to_screen((0.0, 0.0), dst, (200, 100), turned),
+            (400.0, -50.0)
+        ));
+        assert!(close(
+            screen_to_image((400.0, -50.0), dst, (200, 100), turned),
+            (0.0, 0.0)
+        ));
+
+        let mirrored = Orientation {
+            rotation: RotAngle::Up,
+            mirrored: true,
+        };
+        assert!(close(
+            screen_to_image((500.0, 50.0), dst, (200, 100), mirrored),
+            (0.0, 0.0)
+        ));
+
+        // Every orientation maps back to the same pixel
+        for value in 1..=8 {
+            let view = Orientation::from_exif(value);
+            let screen = image_to_screen((30.0, 70.0), dst, (200, 100), view);
+            assert!(close(
+                screen_to_image(screen, dst, (200, 100), view),
+                (30.0, 70.0)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_selection_on_turned_image_swaps_width_and_height() {
+        let dst = Rect::from_center((300, 150), 400, 200);
+        let turned = Orientation {
+            rotation: RotAngle::Left,
+            mirrored: false,
+        };
+        // A wide selection on the window is a tall selection of the image
+        let selection = screen_rect_to_image(Rect::new(250, 100, 100, 20), dst, (200, 100), turned);
+        assert_eq!((selection.width(), selection.height()), (10, 50));
+    }
+
+    #[test]
+    fn test_locked_selection_fits_in_dragged_area_from_anchor() {
+        let free = selection_between((100, 100), (40, 130), None);
+        assert_eq!(free, Rect::new(40, 100, 60, 30));
+
+        // Dragging up and left with a square locked
+        let square = selection_between((100, 100), (40, 70), Some((1, 1)));
+        assert_eq!(square, Rect::new(70, 70, 30, 30));
+        let wide = selection_between((0, 0), (160, 160), Some((16, 9)));
+        assert_eq!(wide, Rect::new(0, 0, 160, 90));
+    }
+
+    #[test]
+    fn test_moved_selection_stays_inside_image() {
+        assert_eq!(
+            shift_inside(Rect::new(-5, 90, 50, 20), (200, 100)),
+            Rect::new(0, 80, 50, 20)
+        );
+    }
+}
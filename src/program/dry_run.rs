@@ -0,0 +1,100 @@
+//! File that contains functionality that pretends to move, copy, link, trash and delete images
+//! in a dry run, updating the images viewed as if it had while leaving every file untouched
+use super::Program;
+use std::path::{Path, PathBuf};
+
+/// The changes a dry run would have made to files, in the order they were asked for
+#[derive(Debug, Default)]
+pub(super) struct DryRun {
+    /// What would have been done, the image and where it would have been written to
+    changes: Vec<(&'static str, PathBuf, Option<PathBuf>)>,
+}
+
+impl DryRun {
+    /// Records that the image at `path` would have been moved, copied, trashed or deleted
+    pub(super) fn record(&mut self, verb: &'static str, path: &Path, dest: Option<&Path>) {
+        self.changes
+            .push((verb, path.to_path_buf(), dest.map(Path::to_path_buf)));
+    }
+
+    /// Everything the dry run would have done, followed by how many images each change affected
+    pub(super) fn summary(&self) -> String {
+        if self.changes.is_empty() {
+            return "Dry run: no files would have been changed".to_string();
+        }
+        let mut lines = vec!["Dry run: no files were changed, riv would have".to_string()];
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for (verb, path, dest) in &self.changes {
+            lines.push(format!("  {} {}", verb, describe(path, dest.as_deref())));
+            match counts.iter_mut().find(|(counted, _)| counted == verb) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((verb, 1)),
+            }
+        }
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(verb, count)| format!("{} {} image(s)", verb, count))
+            .collect();
+        lines.push(counts.join(", "));
+        lines.join("\n")
+    }
+}
+
+/// The image and where it would have been written to
+fn describe(path: &Path, dest: Option<&Path>) -> String {
+    match dest {
+        Some(dest) => format!("{} -> {}", path.display(), dest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Stops tracking the current image and (n-1) next images as if they were trashed or deleted
+    pub(super) fn pretend_remove(&mut self, total: usize, verb: &'static str) -> String {
+        for _ in 0..total {
+            let path = match self.paths.current_image_path() {
+                Some(path) => path.clone(),
+                None => break,
+            };
+            if let Some(dry_run) = self.dry_run.as_mut() {
+                dry_run.record(verb, &path, None);
+            }
+            self.paths.remove_current_image();
+        }
+        self.screen.dirty = true;
+        let mut verb = verb.to_string();
+        verb[..1].make_ascii_uppercase();
+        format!("{} {} image(s) (dry run)", verb, total)
+    }
+
+    /// Returns an error in a dry run, naming the change to files that can't be pretended
+    pub(super) fn reject_dry_run(&self, change: &str) -> Result<(), String> {
+        if self.dry_run.is_some() {
+            return Err(format!("{} is disabled in a dry run", change));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DryRun;
+    use std::path::Path;
+
+    #[test]
+    fn test_summary_lists_changes_and_counts() {
+        let mut dry_run = DryRun::default();
+        assert_eq!(
+            dry_run.summary(),
+            "Dry run: no files would have been changed"
+        );
+        dry_run.record("moved", Path::new("a.jpg"), Some(Path::new("keep/a.jpg")));
+        dry_run.record("trashed", Path::new("b.jpg"), None);
+        dry_run.record("moved", Path::new("c.jpg"), Some(Path::new("keep/c.jpg")));
+        assert_eq!(
+            dry_run.summary(),
+            "Dry run: no files were changed, riv would have\n  moved a.jpg -> keep/a.jpg\n  \
+             trashed b.jpg\n  moved c.jpg -> keep/c.jpg\nmoved 2 image(s), trashed 1 image(s)"
+        );
+    }
+}
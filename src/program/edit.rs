@@ -0,0 +1,146 @@
+//! File that contains functionality that writes changes made while viewing an image back to the
+//! image file, such as saving its rotation
+use super::Program;
+use crate::orientation::{self, Orientation};
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Quality JPEGs are written at when no other quality is asked for
+pub(super) const JPEG_QUALITY: u8 = 90;
+
+impl<'a> Program<'a> {
+    /// Writes the current rotation and flips to the image file, then reloads it
+    /// JPEGs keep their image data and have their EXIF orientation changed, other formats are
+    /// re-encoded with their pixels turned
+    pub(super) fn write_orientation(&mut self) -> Result<String, String> {
+        self.reject_dry_run("writing the orientation")?;
+        self.reject_while_transferring()?;
+        self.reject_in_trash("changed")?;
+        let path = match self.paths.current_image_path() {
+            Some(path) => path.clone(),
+            None => return Err("no image to write the orientation of".to_string()),
+        };
+        if crate::archive::is_entry(&path) {
+            return Err("images inside archives can't be changed".to_string());
+        }
+        let wanted = self.view_orientation();
+        let unchanged = format!("orientation of {} is unchanged", path.display());
+
+        if orientation::is_jpeg(&path) {
+            let bytes = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let current = orientation::jpeg_orientation(&bytes).unwrap_or(1);
+            if Orientation::from_exif(current) == wanted {
+                return Ok(unchanged);
+            }
+            let bytes = orientation::set_jpeg_orientation(&bytes, wanted.exif())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            self.replace_file("write-orientation", &path, |tmp| {
+                fs::write(tmp, &bytes).map_err(|e| e.to_string())
+            })?;
+        } else {
+            if wanted.is_upright() {
+                return Ok(unchanged);
+            }
+            let format = ImageFormat::from_path(&path).map_err(|e| e.to_string())?;
+            // Such as WebP, which can be read but not encoded
+            if !format.can_write() {
+                return Err(format!(
+                    "writing the orientation of {:?} images isn't supported",
+                    format
+                ));
+            }
+            let image = wanted.apply(open_image(&path)?);
+            self.replace_file("write-orientation", &path, |tmp| {
+                write_image(&image, tmp, format, JPEG_QUALITY)
+            })?;
+        }
+
+        // The file now shows the image the way it was being viewed
+        self.screen.dirty = true;
+        Ok(format!("wrote orientation to {}", path.display()))
+    }
+
+    /// Replaces the file at `path` with the file `write` creates at the temporary path it is given,
+    /// journaling it as `op`
+    /// The original is copied to a backup first when backups are enabled
+    pub(super) fn replace_file<F>(&self, op: &str, path: &Path, write: F) -> Result<(), String>
+    where
+        F: FnOnce(&Path) -> Result<(), String>,
+    {
+        if self.backup {
+            let backup = backup_path(path);
+            // An existing backup already holds the original, so it is kept
+            if !backup.exists() {
+                if let Err(e) = fs::copy(path, &backup) {
+                    self.journal
+                        .failed("backup", path, Some(&backup), &e.to_string());
+                    return Err(format!("failed to back up {}: {}", path.display(), e));
+                }
+                self.journal.ok("backup", path, Some(&backup));
+            }
+        }
+        // Written beside the original so it can be renamed over it in one step
+        let tmp = temporary_path(path);
+        let result = write(&tmp)
+            .and_then(|_| {
+                let permissions = fs::metadata(path).map_err(|e| e.to_string())?.permissions();
+                fs::set_permissions(&tmp, permissions).map_err(|e| e.to_string())
+            })
+            .and_then(|_| fs::rename(&tmp, path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp);
+            self.journal.failed(op, path, None, &e);
+            return Err(format!("failed to write {}: {}", path.display(), e));
+        }
+        self.journal.ok(op, path, None);
+        Ok(())
+    }
+}
+
+/// Decodes the image at the path, images inside archives are decoded from memory
+pub(super) fn open_image(path: &Path) -> Result<DynamicImage, String> {
+    let image = if crate::archive::is_entry(path) {
+        image::load_from_memory(&crate::archive::read_entry(path)?)
+    } else {
+        image::open(path)
+    };
+    image.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Writes the image to `path` in `format`, JPEGs are written at `quality` from 1 to 100
+pub(super) fn write_image(
+    image: &DynamicImage,
+    path: &Path,
+    format: ImageFormat,
+    quality: u8,
+) -> Result<(), String> {
+    if format != ImageFormat::Jpeg {
+        return image
+            .save_with_format(path, format)
+            .map_err(|e| e.to_string());
+    }
+    // JPEGs have no transparency and only 8 bits per channel
+    let image = DynamicImage::ImageRgb8(image.to_rgb8());
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+    JpegEncoder::new_with_quality(&mut writer, quality)
+        .encode_image(&image)
+        .map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())
+}
+
+/// Path the original file is backed up to, the path with `.bak` added
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+/// Hidden path beside the file to write its replacement to
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.riv-tmp", name))
+}
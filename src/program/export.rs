@@ -0,0 +1,180 @@
+//! File that contains functionality that exports copies of images converted to another format and
+//! shrunk to fit a size, such as web sized JPEGs of screenshots
+use super::edit::{open_image, write_image, JPEG_QUALITY};
+use super::Program;
+use crate::conflict::free_path;
+use crate::template::{self, Fields};
+use image::imageops::FilterType;
+use image::{GenericImageView, ImageFormat};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Template exported images are named with unless another is set
+pub(super) const DEFAULT_EXPORT_TEMPLATE: &str = "{name}.{ext}";
+
+/// How the exported copies are written
+struct ExportOptions {
+    /// Format the copies are written in
+    format: ImageFormat,
+    /// Extension of the copies
+    ext: String,
+    /// Longest side in pixels the copies are shrunk to fit, None keeps their size
+    max_dim: Option<u32>,
+    /// Quality JPEGs are written at from 1 to 100
+    quality: u8,
+}
+
+impl<'a> Program<'a> {
+    /// Writes copies of the selected images to the destination folder, named with the export
+    /// template
+    /// Arguments are the format to convert to, optionally followed by the longest side in pixels
+    /// to shrink the copies to, 0 keeping their size, and the quality to write JPEGs at
+    pub(super) fn export_images(
+        &mut self,
+        arguments: &str,
+        count: usize,
+    ) -> Result<String, String> {
+        let options = parse_export_arguments(arguments)?;
+        self.reject_dry_run("exporting")?;
+        let images = self.selected_images(count)?;
+        fs::create_dir_all(&self.paths.dest_folder).map_err(|e| e.to_string())?;
+
+        // Store errors for possible future use
+        let mut failures: Vec<String> = Vec::new();
+        for (i, path) in images.iter().enumerate() {
+            let dest = export_destination(
+                &self.paths.dest_folder,
+                &self.export_template,
+                path,
+                i + 1,
+                &options.ext,
+            );
+            let result = dest
+                .as_ref()
+                .map_err(String::clone)
+                .and_then(|dest| export_image(path, dest, &options));
+            let dest = dest.as_deref().ok();
+            match result {
+                Ok(_) => self.journal.ok("export", path, dest),
+                Err(e) => {
+                    eprintln!("Failed to export {}: {}", path.display(), e);
+                    self.journal.failed("export", path, dest, &e);
+                    failures.push(e);
+                }
+            }
+        }
+        if failures.is_empty() {
+            Ok(format!(
+                "exported {} image(s) to {}",
+                images.len(),
+                self.paths.dest_folder.display()
+            ))
+        } else {
+            Err(format!(
+                "Failed to export {} of {} images: {}",
+                failures.len(),
+                images.len(),
+                failures[0]
+            ))
+        }
+    }
+}
+
+/// Parses the arguments of `:export`, the format followed by an optional size and quality
+fn parse_export_arguments(arguments: &str) -> Result<ExportOptions, String> {
+    let mut args = arguments.split_whitespace();
+    let ext = match args.next() {
+        Some(ext) => ext.trim_start_matches('.').to_lowercase(),
+        None => return Err("Command \":export\" requires a format, such as jpg".to_string()),
+    };
+    let format = match ImageFormat::from_extension(&ext) {
+        Some(format) if format.can_write() => format,
+        _ => return Err(format!("Can't export images as \"{}\"", ext)),
+    };
+    let max_dim = match args.next() {
+        Some(size) => match size.parse::<u32>() {
+            Ok(0) => None,
+            Ok(size) => Some(size),
+            Err(_) => return Err(format!("\"{}\" is not a size in pixels", size)),
+        },
+        None => None,
+    };
+    let quality = match args.next() {
+        Some(quality) => match quality.parse::<u8>() {
+            Ok(quality) if (1..=100).contains(&quality) => quality,
+            _ => return Err(format!("\"{}\" is not a quality from 1 to 100", quality)),
+        },
+        None => JPEG_QUALITY,
+    };
+    if let Some(extra) = args.next() {
+        return Err(format!("Unexpected argument \"{}\" to \":export\"", extra));
+    }
+    Ok(ExportOptions {
+        format,
+        ext,
+        max_dim,
+        quality,
+    })
+}
+
+/// Path in the destination folder the `n`th exported image is written to, numbered if a file
+/// is already there
+fn export_destination(
+    dest_folder: &Path,
+    template: &str,
+    path: &Path,
+    n: usize,
+    ext: &str,
+) -> Result<PathBuf, String> {
+    let name = template::expand(template, &Fields { path, n, ext })?;
+    Ok(free_path(&dest_folder.join(name)))
+}
+
+/// Writes a copy of the image at `path` to `dest`, upright and shrunk to fit the size
+fn export_image(path: &Path, dest: &Path, options: &ExportOptions) -> Result<(), String> {
+    // The copy isn't given the EXIF data of the image, so its pixels are turned instead
+    let mut image = crate::orientation::read_orientation(path).apply(open_image(path)?);
+    if let Some(max_dim) = options.max_dim {
+        if image.width() > max_dim || image.height() > max_dim {
+            image = image.resize(max_dim, max_dim, FilterType::Lanczos3);
+        }
+    }
+    write_image(&image, dest, options.format, options.quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_export_arguments;
+    use image::ImageFormat;
+
+    #[test]
+    fn test_export_arguments_default_to_full_size_and_normal_quality() {
+        let options = parse_export_arguments("JPG").unwrap();
+        assert_eq!(options.format, ImageFormat::Jpeg);
+        assert_eq!(options.ext, "jpg");
+        assert_eq!(options.max_dim, None);
+        assert_eq!(options.quality, super::JPEG_QUALITY);
+
+        let options = parse_export_arguments("png 0").unwrap();
+        assert_eq!(options.max_dim, None);
+    }
+
+    #[test]
+    fn test_export_arguments_set_size_and_quality() {
+        let options = parse_export_arguments("jpeg 1920 75").unwrap();
+        assert_eq!(options.format, ImageFormat::Jpeg);
+        assert_eq!(options.ext, "jpeg");
+        assert_eq!(options.max_dim, Some(1920));
+        assert_eq!(options.quality, 75);
+    }
+
+    #[test]
+    fn test_invalid_export_arguments_are_rejected() {
+        assert!(parse_export_arguments("").is_err());
+        assert!(parse_export_arguments("psd").is_err());
+        assert!(parse_export_arguments("jpg big").is_err());
+        assert!(parse_export_arguments("jpg 800 0").is_err());
+        assert!(parse_export_arguments("jpg 800 101").is_err());
+        assert!(parse_export_arguments("jpg 800 90 extra").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! File that contains functionality for running external shell commands on images, such as
+//! handing the current image to an editor or an upload script
+use super::Program;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+impl<'a> Program<'a> {
+    /// Runs the shell command `template` after substituting its placeholders
+    ///
+    /// * `%f` the current image
+    /// * `%F` all marked images
+    /// * `%d` the destination folder
+    /// * `%%` a literal `%`
+    ///
+    /// Blocks until the command exits. Afterwards the current image is reloaded if the command
+    /// changed it, or stops being tracked if the command removed it.
+    pub(super) fn run_external(&mut self, template: &str) -> Result<String, String> {
+        let current = self.paths.current_image_path().cloned();
+        let marked = self.paths.marked_images();
+        let command =
+            substitute_placeholders(template, current.as_ref(), &marked, &self.paths.dest_folder)?;
+        let before = current.as_ref().and_then(file_stamp);
+
+        let output = match shell_command(&command).output() {
+            Ok(output) => output,
+            Err(e) => return Err(format!("failed to run \"{}\": {}", template, e)),
+        };
+
+        if let Some(path) = current {
+            self.refresh_current_image(&path, before);
+        }
+
+        if output.status.success() {
+            return Ok(format!("\"{}\" exited successfully", template));
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.is_empty() {
+            eprintln!("{}", stderr);
+        }
+        // Only one line fits on the infobar, the last line is usually the actual error
+        let last_line = stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("")
+            .trim();
+        match output.status.code() {
+            Some(code) => Err(format!(
+                "\"{}\" exited with status {}: {}",
+                template, code, last_line
+            )),
+            None => Err(format!("\"{}\" was terminated by a signal", template)),
+        }
+    }
+
+    /// Re-stats the image that was current before an external command ran
+    /// Stops tracking it if it was removed, and reloads it if it was modified
+    fn refresh_current_image(&mut self, path: &PathBuf, before: Option<(u64, SystemTime)>) {
+        // The command ran synchronously so the current image is still the same one
+        // Images inside archives can't be changed by the command
+        if self.paths.current_image_path() != Some(path) || crate::archive::is_entry(path) {
+            return;
+        }
+        if !path.exists() {
+            self.paths.remove_current_image();
+            self.screen.dirty = true;
+            return;
+        }
+        if file_stamp(path) != before {
+            self.screen.dirty = true;
+        }
+    }
+}
+
+/// Size and modification time of a file, used to detect if a file changed
+fn file_stamp<P: AsRef<Path>>(path: P) -> Option<(u64, SystemTime)> {
+    let path = path.as_ref();
+    let metadata = path.metadata().ok()?;
+    let modified = metadata.modified().ok()?;
+    Some((metadata.len(), modified))
+}
+
+/// Builds the command that runs `command` through the platform shell
+fn shell_command(command: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Quotes a path so the shell passes it through as a single argument
+fn quote_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", path)
+    } else {
+        // Close the quote, add an escaped quote, then reopen the quote
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+}
+
+/// Replaces the `%f`, `%F`, `%d` and `%%` placeholders in a command template
+/// Unknown placeholders are left untouched
+fn substitute_placeholders(
+    template: &str,
+    current: Option<&PathBuf>,
+    marked: &[PathBuf],
+    dest_folder: &Path,
+) -> Result<String, String> {
+    let mut command = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            command.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('f') => match current {
+                Some(path) => command.push_str(&quote_path(path)),
+                None => return Err("%f requires a current image".to_string()),
+            },
+            Some('F') => {
+                if marked.is_empty() {
+                    return Err("%F requires at least one marked image".to_string());
+                }
+                let quoted: Vec<String> = marked.iter().map(|path| quote_path(path)).collect();
+                command.push_str(&quoted.join(" "));
+            }
+            Some('d') => command.push_str(&quote_path(dest_folder)),
+            Some('%') => command.push('%'),
+            Some(other) => {
+                command.push('%');
+                command.push(other);
+            }
+            None => command.push('%'),
+        }
+    }
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::substitute_placeholders;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_substitute_all_placeholders() {
+        let current = PathBuf::from("/pics/it's.png");
+        let marked = vec![PathBuf::from("/pics/a.png"), PathBuf::from("/pics/b c.png")];
+        let command = substitute_placeholders(
+            "cmd %f %F %d 100%% %x",
+            Some(&current),
+            &marked,
+            Path::new("/keep"),
+        );
+        assert_eq!(
+            command,
+            Ok(
+                "cmd '/pics/it'\\''s.png' '/pics/a.png' '/pics/b c.png' '/keep' 100% %x"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_substitute_marked_without_marks_is_an_error() {
+        let current = PathBuf::from("a.png");
+        let command = substitute_placeholders("echo %F", Some(&current), &[], Path::new("keep"));
+        assert!(command.is_err());
+    }
+
+    #[test]
+    fn test_substitute_current_without_images_is_an_error() {
+        let command = substitute_placeholders("echo %f", None, &[], Path::new("keep"));
+        assert!(command.is_err());
+    }
+}
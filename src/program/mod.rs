@@ -3,27 +3,55 @@
 //! Program contains the program struct, which contains all information needed to run the
 //! event loop and render the images to screen
 
+mod bookmarks;
 mod command_mode;
+mod compare;
+mod crop;
+mod dry_run;
+mod edit;
+mod export;
+mod external;
+mod mouse;
+#[cfg(unix)]
+mod remote;
+mod rename;
 mod render;
+mod session;
+mod spread;
+mod transfer;
+mod transfer_job;
+mod trash_view;
+use self::dry_run::DryRun;
 pub use self::render::*;
+use self::transfer::Transfer;
+use self::transfer_job::TransferJob;
+use crate::bookmarks::{default_bookmarks_path, Bookmarks};
 use crate::cli;
+use crate::conflict::ConflictPolicy;
+use crate::exclude::Exclusions;
+use crate::journal::Journal;
+use crate::orientation::Orientation;
 use crate::paths::{Paths, PathsBuilder};
 use crate::screen::Screen;
 use crate::sort::Sorter;
-use crate::ui::{self, Action, Mode, PanAction, ProcessAction, RotationDirection, ZoomAction};
+use crate::ui::{
+    self, Action, ConfirmAction, ConfirmThresholds, FitMode, Mode, PanAction, ProcessAction,
+    RotAngle, RotationDirection, ZoomAction,
+};
 use core::cmp;
-use fs_extra::file::copy;
-use fs_extra::file::move_file;
 use fs_extra::file::remove;
-use sdl2::rect::Rect;
+use sdl2::event::Event;
+use sdl2::rect::{Point, Rect};
 use sdl2::render::{Canvas, TextureCreator, TextureQuery};
 use sdl2::rwops::RWops;
 use sdl2::ttf::Sdl2TtfContext;
 use sdl2::video::{Window, WindowContext};
 use sdl2::Sdl;
 
+use std::collections::HashSet;
 #[cfg(target_os = "windows")]
 use std::ffi::OsStr;
+#[cfg(target_os = "macos")]
 use std::io::ErrorKind;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
@@ -37,6 +65,39 @@ pub struct Program<'a> {
     paths: Paths,
     ui_state: ui::State<'a>,
     sorter: Sorter,
+    exclusions: Exclusions,
+    /// Whether files are backed up before being changed in place
+    backup: bool,
+    /// Template exported images are named with
+    export_template: String,
+    /// What to do when moving or copying an image to a name that is already taken
+    conflict_policy: ConflictPolicy,
+    /// Whether moved and copied images keep their directories relative to the base directory
+    keep_structure: bool,
+    /// Numbers of images at which destructive actions ask to be confirmed
+    confirm: ConfirmThresholds,
+    /// Records the changes made to files
+    journal: Journal,
+    /// The changes pretended in a dry run, None unless riv is doing a dry run
+    dry_run: Option<DryRun>,
+    /// Images being written by the worker thread, None when no images are being transferred
+    transfer_job: Option<TransferJob>,
+    /// Directory the globs are relative to
+    search_dir: PathBuf,
+    /// The paths, directories or globs the images were found with
+    globs: Vec<String>,
+    /// Name of the session saved on exit, None if no session is being kept
+    session: Option<String>,
+    /// Images bookmarked by letter and folders bookmarked by name
+    bookmarks: Bookmarks,
+    /// Where the images trashed since starting were trashed from
+    trashed: HashSet<PathBuf>,
+    /// The images viewed before the trash was opened, None unless viewing the trash
+    trash_view: Option<trash_view::TrashView>,
+    /// Whether the command being run was sent by remote control
+    remote: bool,
+    #[cfg(unix)]
+    ipc: Option<crate::ipc::IpcServer>,
 }
 
 impl<'a> Program<'a> {
@@ -84,7 +145,24 @@ impl<'a> Program<'a> {
         let paths = PathsBuilder::new(images, dest_folder, base_dir)
             .with_maximum_viewable(max_viewable)
             .build();
-        Ok(Program {
+        let bindings = args.bindings.into_iter().collect();
+
+        #[cfg(unix)]
+        let ipc = match args.socket {
+            Some(path) => Some(
+                crate::ipc::IpcServer::bind(&path)
+                    .map_err(|e| format!("Failed to listen on \"{}\": {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        #[cfg(not(unix))]
+        {
+            if args.socket.is_some() {
+                eprintln!("Remote control is only supported on unix platforms");
+            }
+        }
+
+        let mut program = Program {
             screen: Screen {
                 sdl_context,
                 canvas,
@@ -93,25 +171,79 @@ impl<'a> Program<'a> {
                 mono_font,
                 last_index: None,
                 last_texture: None,
+                last_orientation: Orientation::default(),
+                second_texture: None,
+                second_orientation: Orientation::default(),
+                pinned_texture: None,
+                pinned_orientation: Orientation::default(),
                 dirty: false,
             },
             paths,
             ui_state: ui::State {
                 fullscreen: args.fullscreen,
+                bindings,
+                fit_mode: Some(args.fit),
+                default_fit: args.fit,
+                keep_view: args.keep_view,
                 ..Default::default()
             },
             sorter,
-        })
+            exclusions: args.exclusions,
+            backup: args.backup,
+            export_template: export::DEFAULT_EXPORT_TEMPLATE.to_string(),
+            conflict_policy: args.on_conflict,
+            keep_structure: args.keep_structure,
+            confirm: args.confirm,
+            // Nothing happens to files in a dry run, so there is nothing to journal
+            journal: Journal::new(if args.dry_run { None } else { args.journal }),
+            dry_run: if args.dry_run {
+                Some(DryRun::default())
+            } else {
+                None
+            },
+            transfer_job: None,
+            trashed: HashSet::new(),
+            trash_view: None,
+            search_dir: args.search_dir,
+            globs: args.globs,
+            session: args.session,
+            bookmarks: Bookmarks::open(default_bookmarks_path()),
+            remote: false,
+            #[cfg(unix)]
+            ipc,
+        };
+        if let Some(resume) = &args.resume {
+            program.restore_position(resume);
+        }
+        Ok(program)
     }
 
     /// Toggle whether actual size or scaled image is rendered.
     pub fn toggle_fit(&mut self) -> Result<(), String> {
         let error = 0.001;
         if (self.ui_state.scale - 1.0).abs() > error {
-            self.ui_state.scale = 1.0;
+            self.ui_state.fit_mode = Some(FitMode::Actual);
+        } else if self.ui_state.default_fit == FitMode::Actual {
+            self.ui_state.fit_mode = Some(FitMode::Fit);
         } else {
-            self.ui_state.scale = self.calculate_scale_for_fit();
+            self.ui_state.fit_mode = Some(self.ui_state.default_fit);
         }
+        self.ui_state.scale = self.calculate_scale_for_fit();
+        self.render_screen(false)
+    }
+
+    /// Switches the current image to the next fit mode `times` times and centres it
+    fn cycle_fit_mode(&mut self, times: usize) -> Result<(), String> {
+        let mut mode = self.ui_state.fit_mode.unwrap_or(self.ui_state.default_fit);
+        for _ in 0..times {
+            mode = mode.next();
+        }
+        self.ui_state.fit_mode = Some(mode);
+        self.ui_state.scale = self.calculate_scale_for_fit();
+        self.ui_state.pan_x = 0.0;
+        self.ui_state.pan_y = 0.0;
+        self.ui_state.mode = Mode::Success(format!("fit mode {}", mode));
+        self.ui_state.rerender_time = Some(Instant::now());
         self.render_screen(false)
     }
 
@@ -122,26 +254,67 @@ impl<'a> Program<'a> {
         self.render_screen(false)
     }
 
-    // Calculates the scale required to fit large images to screen
-    fn calculate_scale_for_fit(&self) -> f32 {
-        if let Some(tex) = self.screen.last_texture.as_ref() {
-            let query = tex.query();
-            let (src_x, src_y) = (query.width, query.height);
-            let target = self.screen.canvas.viewport();
-            let (dst_x, dst_y) = (target.width(), target.height());
-            // case 1: both source dimensions smaller
-            if src_x < dst_x && src_y < dst_y {
-                return 1.0;
+    /// Size of everything being displayed at a scale of 1.0
+    /// In a two page spread this is both images, the second scaled to the height of the first
+    /// Each image is turned the way its file says to show it
+    fn content_size(&self) -> Option<(u32, u32)> {
+        let texture = self.screen.last_texture.as_ref()?;
+        let query = render::upright_query(texture, self.screen.last_orientation);
+        let first = (query.width, query.height);
+        match self.screen.second_texture.as_ref() {
+            Some(second) => {
+                let query = render::upright_query(second, self.screen.second_orientation);
+                let second = (query.width, query.height);
+                Some((first.0 + spread::matched_width(first, second), first.1))
             }
-            // case 2: source aspect ratio is larger
-            if src_x as f32 / src_y as f32 > dst_x as f32 / dst_y as f32 {
-                return dst_x as f32 / src_x as f32;
+            None => Some(first),
+        }
+    }
+
+    /// Turns and flips applied to the view, on top of the orientation each image is shown in
+    fn view_turn(&self) -> Orientation {
+        Orientation::from_view(
+            self.ui_state.rot_angle,
+            self.ui_state.flip_horizontal,
+            self.ui_state.flip_vertical,
+        )
+    }
+
+    /// Orientation the current image is shown in, the way its file says to show it and then
+    /// turned and flipped by the view
+    fn view_orientation(&self) -> Orientation {
+        self.screen.last_orientation.then(self.view_turn())
+    }
+
+    /// Size of the images as they appear on screen at scale 1.0, swapped when on their side
+    fn displayed_size(&self) -> Option<(u32, u32)> {
+        let size = self.content_size()?;
+        Some(rotated_size(size, &self.ui_state.rot_angle))
+    }
+
+    // Calculates the scale required to fit the image to screen with the current fit mode
+    // An image zoomed by hand is fit with the default fit mode
+    fn calculate_scale_for_fit(&self) -> f32 {
+        match self.displayed_size() {
+            Some(src) => {
+                let target = self.image_area();
+                let mode = self.ui_state.fit_mode.unwrap_or(self.ui_state.default_fit);
+                mode.scale(src, (target.width(), target.height()))
             }
-            // case 3: source aspect ratio is smaller
-            dst_y as f32 / src_y as f32
-        } else {
-            1.0
+            None => 1.0,
+        }
+    }
+
+    /// Rotates the image a quarter turn, refitting it as its width and height swap
+    fn rotate(&mut self, direction: &RotationDirection) -> Result<(), String> {
+        self.ui_state.rot_angle = match direction {
+            RotationDirection::Clockwise => self.ui_state.rot_angle.rot_clockwise(),
+            RotationDirection::CounterClockwise => self.ui_state.rot_angle.rot_clockclockwise(),
+        };
+        if self.ui_state.fit_mode.is_some() {
+            self.ui_state.scale = self.calculate_scale_for_fit();
         }
+        self.render_screen(false)
     }
 
     /// Flip image vertically
@@ -159,13 +332,21 @@ impl<'a> Program<'a> {
     }
 
     fn increment(&mut self, step: usize) -> Result<(), String> {
-        self.paths.increment(step);
+        if self.ui_state.spread {
+            self.spread_increment(step);
+        } else {
+            self.paths.increment(step);
+        }
         self.render_screen(false)
     }
 
     /// Moves tracking current image down by `step`
     fn decrement(&mut self, step: usize) -> Result<(), String> {
-        self.paths.decrement(step);
+        if self.ui_state.spread {
+            self.spread_decrement(step);
+        } else {
+            self.paths.decrement(step);
+        }
         self.render_screen(false)
     }
 
@@ -263,101 +444,59 @@ impl<'a> Program<'a> {
     }
 
     fn calc_x_step(&self) -> f32 {
-        if let Some(tex) = self.screen.last_texture.as_ref() {
-            let src_w = tex.query().width;
-            let dst_w = self.screen.canvas.viewport().width();
-            let x_diff = (dst_w as f32 - (src_w as f32 * self.ui_state.scale)) / 2.0;
-            (PAN_PIXELS / x_diff).abs()
-        } else {
-            0.0
+        match self.displayed_size() {
+            Some((src_w, _)) => pan_step(src_w, self.image_area().width(), self.ui_state.scale),
+            None => 0.0,
         }
     }
 
     fn calc_y_step(&self) -> f32 {
-        if let Some(tex) = self.screen.last_texture.as_ref() {
-            let src_h = tex.query().height;
-            let dst_h = self.screen.canvas.viewport().height();
-            let y_diff = (dst_h as f32 - (src_h as f32 * self.ui_state.scale)) / 2.0;
-            (PAN_PIXELS / y_diff).abs()
-        } else {
-            0.0
+        match self.displayed_size() {
+            Some((_, src_h)) => pan_step(src_h, self.image_area().height(), self.ui_state.scale),
+            None => 0.0,
         }
     }
 
-    fn construct_dest_filepath(&self, src_path: &PathBuf) -> Result<PathBuf, String> {
-        match std::fs::create_dir_all(&self.paths.dest_folder) {
-            Ok(_) => (),
-            Err(e) => match e.kind() {
-                ErrorKind::AlreadyExists => (),
-                _ => return Err(e.to_string()),
-            },
+    /// The current image and (n-1) next images, capped at the last viewable image
+    fn images_from_current(&self, amount: usize) -> Result<&[PathBuf], String> {
+        let current_index = match self.paths.index() {
+            Some(i) => i,
+            None => return Err("no images".to_string()),
         };
 
-        let cur_filename = match src_path.file_name() {
-            Some(f) => f,
-            None => return Err("failed to read filename for current image".to_string()),
-        };
-        let newname = PathBuf::from(&self.paths.dest_folder).join(cur_filename);
-        Ok(newname)
+        let range = current_index..=(current_index.saturating_add(amount.max(1) - 1));
+        match self.paths.get_range(&range) {
+            Some(paths) => Ok(paths),
+            None => Err(format!(
+                "Image range {}..={} is out of range",
+                range.start(),
+                range.end()
+            )),
+        }
+    }
+
+    /// Images a command works on, the current image and (n-1) next images when a count was typed
+    /// before the command, otherwise the marked images, or the current image if none are marked
+    fn selected_images(&self, count: usize) -> Result<Vec<PathBuf>, String> {
+        let marked = self.paths.marked_images();
+        if count > 1 || marked.is_empty() {
+            return self.images_from_current(count).map(|paths| paths.to_vec());
+        }
+        Ok(marked)
     }
 
     /// Copies the current image and (n-1) next images
     /// Does nothing if supplied 0 for an amount
-    fn copy_images(&self, amount: usize) -> Result<String, String> {
+    fn copy_images(&mut self, amount: usize) -> Result<String, String> {
         if amount == 0 {
             return Ok("0 images asked to copy".to_string());
         }
 
-        let current_index = match self.paths.index() {
-            Some(i) => i,
-            None => return Err("no images to copy".to_string()),
-        };
-
-        let copy_range = current_index..=(current_index.saturating_add(amount - 1));
-        let paths = self.paths.get_range(&copy_range);
-        let paths = match paths {
-            Some(paths) => paths,
-            None => {
-                return Err(format!(
-                    "Image range {}..={} is out of range",
-                    copy_range.start(),
-                    copy_range.end()
-                ))
-            }
-        };
-
-        // Store errors for possible future use
-        let mut failures: Vec<String> = Vec::new();
-        for imagepath in paths {
-            let newname = match self.construct_dest_filepath(imagepath) {
-                Ok(path) => path,
-                Err(e) => {
-                    eprintln!("{}", e);
-                    failures.push(e);
-                    continue;
-                }
-            };
-
-            let opt = &fs_extra::file::CopyOptions::new();
-            if let Err(e) = copy(imagepath, newname, opt).map_err(|e| e.to_string()) {
-                eprintln!("{}", e);
-                failures.push(e);
-                continue;
-            }
-        }
-        if failures.is_empty() {
-            Ok(format!(
-                "copied {} image(s) to {} succesfully",
-                paths.len(),
-                self.paths.dest_folder.to_str().unwrap(),
-            ))
-        } else {
-            Err(format!(
-                "Failed to copy {} of {} images",
-                failures.len(),
-                paths.len()
-            ))
+        if self.paths.index().is_none() {
+            return Err("no images to copy".to_string());
         }
+        let images = self.images_from_current(amount)?.to_vec();
+        self.transfer_images(&images, Transfer::Copy)
     }
 
     /// Moves the current image and (n-1) next images
@@ -367,53 +506,44 @@ impl<'a> Program<'a> {
             return Ok("0 images asked to move".to_string());
         }
 
-        let current_index = match self.paths.index() {
-            Some(i) => i,
-            None => return Err("no images to move".to_string()),
-        };
-
-        // Safe to unwrap as max_index is always present if index is present
-        let max_index = self.paths.max_viewable_index().unwrap();
-
-        // Compute actual number of images to remove
-        // Cap at max index. Add 1 incase max index == current_index
-        let total_removes =
-            std::cmp::min(current_index + amount - 1, max_index) - current_index + 1;
-        // Store errors for possible future use
-        let mut failures: Vec<String> = Vec::new();
-        for _ in 0..total_removes {
-            let current_path = self.paths.current_image_path().unwrap();
-            let newname = self.construct_dest_filepath(current_path)?;
-            let opt = &fs_extra::file::CopyOptions::new();
-
-            // Attempt to move as many images as possible
-            if let Err(e) = move_file(current_path, &newname, opt) {
-                eprintln!("{}", e);
-                failures.push(e.to_string());
-                continue;
-            }
-            // Only if successful, remove image from tracked images
-            self.paths.remove_current_image();
+        if self.paths.index().is_none() {
+            return Err("no images to move".to_string());
         }
+        let images = self.images_from_current(amount)?.to_vec();
+        let result = self.transfer_images(&images, Transfer::Move);
 
-        // Moving the image automatically advanced to next image
+        // Moving the images automatically advanced to the next image
         // Adjust our view to reflect this
         self.screen.dirty = true;
         self.render_screen(false)?;
-        if failures.is_empty() {
-            let success_msg = format!(
-                "moved {} image(s) succesfully to {}",
-                total_removes,
-                self.paths.dest_folder.to_str().unwrap()
-            );
-            Ok(success_msg)
-        } else {
-            Err(format!(
-                "Failed to move {} of {} images",
-                failures.len(),
-                total_removes,
-            ))
+        result
+    }
+
+    /// Hardlinks or symlinks the current image and (n-1) next images when a count was typed,
+    /// otherwise the marked images or the current image, into the destination folder
+    fn link_images(&mut self, count: usize, transfer: Transfer) -> Result<String, String> {
+        if count == 0 {
+            return Ok("0 images asked to link".to_string());
         }
+
+        let images = self.selected_images(count)?;
+        self.transfer_images(&images, transfer)
+    }
+
+    /// Returns an error if any of the `amount` images starting at `start` are inside an archive,
+    /// as they can't be removed on their own
+    fn reject_archive_entries(
+        &self,
+        start: usize,
+        amount: usize,
+        verb: &str,
+    ) -> Result<(), String> {
+        let range = start..=(start + amount - 1);
+        let paths = self.paths.get_range(&range).unwrap_or(&[]);
+        if paths.iter().any(|path| crate::archive::is_entry(path)) {
+            return Err(format!("images inside archives can't be {}", verb));
+        }
+        Ok(())
     }
 
     /// Trashes image currently being viewed
@@ -434,13 +564,21 @@ impl<'a> Program<'a> {
         // Cap at max index. Add 1 incase max index == current_index
         let total_trashes =
             std::cmp::min(current_index + amount - 1, max_index) - current_index + 1;
+        self.reject_archive_entries(current_index, total_trashes, "trashed")?;
+        self.reject_in_trash("trashed")?;
+        self.reject_while_transferring()?;
+        if self.dry_run.is_some() {
+            return Ok(self.pretend_remove(total_trashes, "trashed"));
+        }
 
         // Store errors for possible future use
 
         let mut failures: Vec<String> = Vec::new();
         // Attempt to trash as many images as possible;
         for _ in 0..total_trashes {
-            let current_path = self.paths.current_image_path().unwrap();
+            let current_path = self.paths.current_image_path().unwrap().clone();
+            // Remembered so the trash can show the images riv trashed
+            let original = std::fs::canonicalize(&current_path).ok();
 
             #[cfg(target_os = "windows")]
             {
@@ -451,6 +589,8 @@ impl<'a> Program<'a> {
 
                 if let Err(e) = trash_result {
                     eprintln!("{}", e);
+                    self.journal
+                        .failed("trash", &current_path, None, &e.to_string());
                     failures.push(e.to_string());
                     continue;
                 }
@@ -460,6 +600,8 @@ impl<'a> Program<'a> {
                 let trash_result = trash::move_to_trash(&current_path);
                 if let Err(e) = trash_result {
                     eprintln!("{}", e);
+                    self.journal
+                        .failed("trash", &current_path, None, &e.to_string());
                     failures.push(e.to_string());
                     continue;
                 }
@@ -484,7 +626,9 @@ impl<'a> Program<'a> {
 
                 if !output.status.success() {
                     eprintln!("{:?}", &output);
-                    failures.push(format!("{:?}: {:?}", output.status, output.stderr));
+                    let failure = format!("{:?}: {:?}", output.status, output.stderr);
+                    self.journal.failed("trash", &current_path, None, &failure);
+                    failures.push(failure);
                     continue;
                 }
             }
@@ -492,6 +636,10 @@ impl<'a> Program<'a> {
             return Err("Trash support for OS not supported".to_string());
 
             // Only if successful, remove image from tracked images
+            // Journaled where the trash records it was trashed from, so later runs can find it
+            let source = original.as_deref().unwrap_or(&current_path);
+            self.journal.ok("trash", source, None);
+            self.trashed.extend(original);
             self.paths.remove_current_image();
         }
 
@@ -529,6 +677,12 @@ impl<'a> Program<'a> {
         // Cap at max index. Add 1 incase max index == current_index
         let total_removes =
             std::cmp::min(current_index + amount - 1, max_index) - current_index + 1;
+        self.reject_archive_entries(current_index, total_removes, "deleted")?;
+        self.reject_in_trash("deleted")?;
+        self.reject_while_transferring()?;
+        if self.dry_run.is_some() {
+            return Ok(self.pretend_remove(total_removes, "deleted"));
+        }
 
         // Store errors for possible future use
         let mut failures: Vec<String> = Vec::new();
@@ -537,10 +691,13 @@ impl<'a> Program<'a> {
             let current_path = self.paths.current_image_path().unwrap();
             if let Err(e) = remove(current_path) {
                 eprintln!("{}", e);
+                self.journal
+                    .failed("delete", current_path, None, &e.to_string());
                 failures.push(e.to_string());
                 continue;
             }
             // Only if successful, remove image from tracked images
+            self.journal.ok("delete", current_path, None);
             self.paths.remove_current_image();
         }
 
@@ -560,6 +717,24 @@ impl<'a> Program<'a> {
         }
     }
 
+    /// Toggles the mark on the current image and (n-1) next images
+    fn toggle_marks(&mut self, amount: usize) -> Result<(), String> {
+        let current_index = match self.paths.index() {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        // Safe to unwrap as max_index is always present if index is present
+        let max_index = self.paths.max_viewable_index().unwrap();
+        let last_index = cmp::min(
+            current_index.saturating_add(amount.saturating_sub(1)),
+            max_index,
+        );
+        for index in current_index..=last_index {
+            self.paths.toggle_mark(index);
+        }
+        self.render_screen(false)
+    }
+
     /// Jumps to specific image
     /// Caps at artificial length or last image if index supplied is too large
     fn jump_to_image_index(&mut self, index: usize) -> Result<(), String> {
@@ -592,6 +767,20 @@ impl<'a> Program<'a> {
                     // Force renders in order to remove "Command" and other info from bar
                     self.render_screen(true)?;
                 }
+                Mode::Crop => {
+                    self.run_crop_mode()?;
+                    self.render_screen(false)?;
+                }
+                Mode::Rename => {
+                    self.run_rename_mode()?;
+                    self.render_screen(false)?;
+                }
+                // Conflicts are only asked about while moving or copying images
+                Mode::Conflict(..) => self.ui_state.mode = Mode::Normal,
+                Mode::Confirm(..) => {
+                    self.run_confirm_mode()?;
+                    self.render_screen(false)?;
+                }
                 Mode::Error(..) => {
                     self.render_screen(false)?;
                     self.ui_state.mode = Mode::Normal;
@@ -603,9 +792,74 @@ impl<'a> Program<'a> {
                 Mode::Exit => break 'main_loop,
             }
         }
+        // Images left to write are skipped, waiting only for the image being written
+        if self.cancel_transfer().is_ok() {
+            match self.poll_transfer(true) {
+                Some(Ok(s)) => println!("{}", s),
+                Some(Err(e)) => eprintln!("{}", e),
+                None => {}
+            }
+        }
+        if let Some(dry_run) = &self.dry_run {
+            println!("{}", dry_run.summary());
+        }
+        self.save_session_on_exit();
+        Ok(())
+    }
+
+    /// Mode that asks whether to perform the pending destructive action, performing it only if
+    /// confirmed
+    fn run_confirm_mode(&mut self) -> Result<(), String> {
+        self.ui_state.render_infobar = true;
+        self.render_screen(false)?;
+        self.run_modal(|program, event| {
+            match ui::process_yes_no_mode(event) {
+                ConfirmAction::Confirm => {
+                    program.ui_state.mode = Mode::Normal;
+                    if let Some(action) = program.ui_state.pending.take() {
+                        program.ui_state.confirmed = true;
+                        program.dispatch_normal(action)?;
+                    }
+                }
+                ConfirmAction::Cancel => program.ui_state.mode = Mode::Normal,
+                ConfirmAction::Quit => program.ui_state.mode = Mode::Exit,
+                ConfirmAction::ReRender => {
+                    program.render_screen(false)?;
+                    return Ok(None);
+                }
+                ConfirmAction::Noop => return Ok(None),
+            }
+            Ok(Some(()))
+        })?;
+        self.ui_state.pending = None;
         Ok(())
     }
 
+    /// Runs a mode that takes over the input, such as asking a question, passing each event to
+    /// `handle` until it returns what the mode ends with
+    fn run_modal<T, F>(&mut self, mut handle: F) -> Result<T, String>
+    where
+        F: FnMut(&mut Self, &Event) -> Result<Option<T>, String>,
+    {
+        loop {
+            for event in self.screen.sdl_context.event_pump()?.poll_iter() {
+                if let Some(result) = handle(self, &event)? {
+                    return Ok(result);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(1000 / 60));
+        }
+    }
+
+    /// Number of images an action on the current image and (n-1) next images affects, capped at
+    /// the last viewable image
+    fn affected_images(&self, amount: usize) -> usize {
+        match (self.paths.index(), self.paths.max_viewable_index()) {
+            (Some(index), Some(max_index)) => amount.min(max_index - index + 1),
+            _ => 0,
+        }
+    }
+
     /// Mode to input how many times to repeat a normal mode action
     /// Previous input from normal mode is in `self.ui_state.repeat`
     fn run_multi_normal_mode(&mut self) -> Result<(), String> {
@@ -674,7 +928,19 @@ impl<'a> Program<'a> {
     // Allow cognitive complexity lint since we need to match every Action
     // Note: complexity could be simplified by splittng out Command mode and Normal mode actions
     #[allow(clippy::cognitive_complexity)]
-    fn dispatch_normal(&mut self, process_action: ProcessAction) -> Result<CompleteType, String> {
+    fn dispatch_normal(
+        &mut self,
+        process_action: ProcessAction<'a>,
+    ) -> Result<CompleteType, String> {
+        // Destructive actions on enough images are asked about first, unless already confirmed
+        if !std::mem::replace(&mut self.ui_state.confirmed, false) {
+            let count = self.affected_images(process_action.times);
+            if let Some(question) = self.confirm.question(&process_action.action, count) {
+                self.ui_state.mode = Mode::Confirm(question);
+                self.ui_state.pending = Some(process_action);
+                return Ok(CompleteType::Break);
+            }
+        }
         match process_action {
             ProcessAction { action, times } => match action {
                 Action::Quit => {
@@ -688,6 +954,7 @@ impl<'a> Program<'a> {
                 }
                 Action::ReRender => self.render_screen(false)?,
                 Action::SwitchCommandMode => {
+                    self.ui_state.command_count = times;
                     self.ui_state.mode = Mode::Command(String::new());
                     return Ok(CompleteType::Break);
                 }
@@ -696,6 +963,14 @@ impl<'a> Program<'a> {
                     return Ok(CompleteType::Break);
                 }
                 Action::ToggleFit => self.toggle_fit()?,
+                Action::Crop => {
+                    self.start_crop();
+                    return Ok(CompleteType::Break);
+                }
+                Action::CycleFitMode => {
+                    self.cycle_fit_mode(times)?;
+                    return Ok(CompleteType::Break);
+                }
                 Action::FlipHorizontal => self.flip_horizontal()?,
                 Action::FlipVertical => self.flip_vertical()?,
                 Action::CenterImage => self.center_image()?,
@@ -707,62 +982,121 @@ impl<'a> Program<'a> {
                 Action::SkipBack => self.skip_backward(times)?,
                 Action::Zoom(ZoomAction::In) => self.zoom_in(times)?,
                 Action::Zoom(ZoomAction::Out) => self.zoom_out(times)?,
-                Action::Rotate(RotationDirection::Clockwise) => {
-                    self.ui_state.rot_angle = self.ui_state.rot_angle.rot_clockwise();
-                    self.render_screen(false)?;
-                }
-                Action::Rotate(RotationDirection::CounterClockwise) => {
-                    self.ui_state.rot_angle = self.ui_state.rot_angle.rot_clockclockwise();
-                    self.render_screen(false)?;
-                }
+                Action::ZoomAt(zoom, x, y) => self.zoom_at(&zoom, times, x, y)?,
+                Action::Drag(dx, dy) => self.drag(dx, dy)?,
+                Action::Rotate(direction) => self.rotate(&direction)?,
                 Action::Pan(PanAction::Left) => self.pan_left(times)?,
                 Action::Pan(PanAction::Right) => self.pan_right(times)?,
                 Action::Pan(PanAction::Up) => self.pan_up(times)?,
                 Action::Pan(PanAction::Down) => self.pan_down(times)?,
-                Action::Copy => match self.copy_images(times) {
+                Action::Copy | Action::Move | Action::Hardlink | Action::Symlink => {
+                    let result = match action {
+                        Action::Copy => self.copy_images(times),
+                        Action::Move => self.move_images(times),
+                        Action::Hardlink => self.link_images(times, Transfer::Hardlink),
+                        _ => self.link_images(times, Transfer::Symlink),
+                    };
+                    match result {
+                        // The app was quit while asking what to do with a taken name
+                        _ if self.ui_state.mode == Mode::Exit => {}
+                        Ok(s) => {
+                            self.ui_state.mode = Mode::Success(s);
+                            self.ui_state.rerender_time = Some(Instant::now());
+                        }
+                        Err(e) => self.ui_state.mode = Mode::Error(e),
+                    }
+                    return Ok(CompleteType::Break);
+                }
+                Action::Delete => match self.delete_images(times) {
                     Ok(s) => {
                         self.ui_state.mode = Mode::Success(s);
                         self.ui_state.rerender_time = Some(Instant::now());
                         return Ok(CompleteType::Break);
                     }
                     Err(e) => {
-                        self.ui_state.mode = Mode::Error(format!("Failed to copy file: {}", e));
+                        self.ui_state.mode = Mode::Error(format!("Failed to delete file: {}", e));
                         return Ok(CompleteType::Break);
                     }
                 },
-                Action::Move => match self.move_images(times) {
+                Action::Trash => match self.trash_images(times) {
                     Ok(s) => {
                         self.ui_state.mode = Mode::Success(s);
                         self.ui_state.rerender_time = Some(Instant::now());
                         return Ok(CompleteType::Break);
                     }
                     Err(e) => {
-                        self.ui_state.mode = Mode::Error(format!("Failed to move file: {}", e));
+                        self.ui_state.mode = Mode::Error(format!("Failed to delete file: {}", e));
                         return Ok(CompleteType::Break);
                     }
                 },
-                Action::Delete => match self.delete_images(times) {
-                    Ok(s) => {
-                        self.ui_state.mode = Mode::Success(s);
-                        self.ui_state.rerender_time = Some(Instant::now());
+                Action::SetBookmark(letter) => {
+                    match self.set_image_bookmark(letter) {
+                        Ok(s) => {
+                            self.ui_state.mode = Mode::Success(s);
+                            self.ui_state.rerender_time = Some(Instant::now());
+                        }
+                        Err(e) => self.ui_state.mode = Mode::Error(e),
+                    }
+                    return Ok(CompleteType::Break);
+                }
+                Action::JumpToBookmark(letter) => {
+                    if let Err(e) = self.jump_to_bookmark(letter) {
+                        self.ui_state.mode = Mode::Error(e);
                         return Ok(CompleteType::Break);
                     }
-                    Err(e) => {
-                        self.ui_state.mode = Mode::Error(format!("Failed to delete file: {}", e));
+                }
+                Action::CancelTransfer => {
+                    match self.cancel_transfer() {
+                        Ok(s) => {
+                            self.ui_state.mode = Mode::Success(s);
+                            self.ui_state.rerender_time = Some(Instant::now());
+                        }
+                        Err(e) => self.ui_state.mode = Mode::Error(e),
+                    }
+                    return Ok(CompleteType::Break);
+                }
+                Action::ToggleMark => self.toggle_marks(times)?,
+                Action::ToggleSpread => self.toggle_spread()?,
+                Action::SwapSpreadDirection => {
+                    self.swap_spread_direction()?;
+                    return Ok(CompleteType::Break);
+                }
+                Action::ToggleCompare => {
+                    self.toggle_compare()?;
+                    if self.ui_state.mode != Mode::Normal {
                         return Ok(CompleteType::Break);
                     }
-                },
-                Action::Trash => match self.trash_images(times) {
-                    Ok(s) => {
-                        self.ui_state.mode = Mode::Success(s);
-                        self.ui_state.rerender_time = Some(Instant::now());
+                }
+                Action::SwapCompare => {
+                    self.swap_compare()?;
+                    if self.ui_state.mode != Mode::Normal {
                         return Ok(CompleteType::Break);
                     }
-                    Err(e) => {
-                        self.ui_state.mode = Mode::Error(format!("Failed to delete file: {}", e));
+                }
+                Action::ToggleFlicker => {
+                    self.toggle_flicker()?;
+                    if self.ui_state.mode != Mode::Normal {
                         return Ok(CompleteType::Break);
                     }
-                },
+                }
+                Action::Exec(command) => {
+                    // Keys can be bound to command mode commands as well as shell commands
+                    if let Some(command) = command.strip_prefix(':') {
+                        self.ui_state.command_count = times;
+                        self.execute_command(command.to_string())?;
+                        return Ok(CompleteType::Break);
+                    }
+                    match self.run_external(&command) {
+                        Ok(s) => {
+                            self.ui_state.mode = Mode::Success(s);
+                            self.ui_state.rerender_time = Some(Instant::now());
+                        }
+                        Err(e) => {
+                            self.ui_state.mode = Mode::Error(e);
+                        }
+                    }
+                    return Ok(CompleteType::Break);
+                }
                 Action::Noop => return Ok(CompleteType::Complete),
                 _ => return Ok(CompleteType::Complete),
             },
@@ -807,6 +1141,30 @@ impl<'a> Program<'a> {
                 }
             }
 
+            #[cfg(unix)]
+            {
+                if let CompleteType::Break = self.process_remote_commands()? {
+                    break 'mainloop;
+                }
+            }
+
+            let progress = self.ui_state.progress.clone();
+            match self.poll_transfer(false) {
+                Some(Ok(s)) => {
+                    self.ui_state.mode = Mode::Success(s);
+                    self.ui_state.rerender_time = Some(Instant::now());
+                    break 'mainloop;
+                }
+                Some(Err(e)) => {
+                    self.ui_state.mode = Mode::Error(e);
+                    break 'mainloop;
+                }
+                None if self.ui_state.progress != progress => self.render_screen(false)?,
+                None => {}
+            }
+
+            self.update_flicker()?;
+
             if let Some(ts) = self.ui_state.rerender_time {
                 if Instant::now().duration_since(ts) > Duration::from_millis(1500) {
                     self.ui_state.rerender_time = None;
@@ -822,14 +1180,63 @@ impl<'a> Program<'a> {
 
 /// make dst determines the parameters of a rectangle required to place an image correctly in
 /// the window
-fn make_dst(tq: &TextureQuery, vp: &Rect, scale: f32, pan_x: f32, pan_y: f32) -> Rect {
-    let x_diff = (vp.width() as f32 - (tq.width as f32 * scale)) / 2.0;
-    let y_diff = (vp.height() as f32 - (tq.height as f32 * scale)) / 2.0;
-    let x = (x_diff - x_diff * pan_x) as i32;
-    let y = (y_diff - y_diff * pan_y) as i32;
-    let width = (tq.width as f32 * scale) as u32;
-    let height = (tq.height as f32 * scale) as u32;
-    Rect::new(x, y, width, height)
+fn make_dst(
+    tq: &TextureQuery,
+    vp: &Rect,
+    scale: f32,
+    pan_x: f32,
+    pan_y: f32,
+    rot: &RotAngle,
+) -> Rect {
+    let (width, height) = (tq.width as f32 * scale, tq.height as f32 * scale);
+    // The box the image covers on screen once rotated
+    let (shown_width, shown_height) = if rot.is_sideways() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let x_diff = (vp.width() as f32 - shown_width) / 2.0;
+    let y_diff = (vp.height() as f32 - shown_height) / 2.0;
+    // Images are rotated about their centre, so the unrotated image shares the centre of the box
+    let x = (x_diff - x_diff * pan_x + (shown_width - width) / 2.0) as i32;
+    let y = (y_diff - y_diff * pan_y + (shown_height - height) / 2.0) as i32;
+    Rect::new(x, y, width as u32, height as u32)
+}
+
+/// Moves `rect` to where it ends up when everything is rotated by `rot` about `centre`
+/// Used to place each part of an image made of several textures, which are rotated separately
+fn rotate_about(rect: Rect, centre: Point, rot: &RotAngle) -> Rect {
+    let (dx, dy) = (
+        rect.center().x() - centre.x(),
+        rect.center().y() - centre.y(),
+    );
+    // Screen coordinates point down, so a clockwise turn takes right to down
+    let (dx, dy) = match rot {
+        RotAngle::Up => (dx, dy),
+        RotAngle::Right => (-dy, dx),
+        RotAngle::Down => (-dx, -dy),
+        RotAngle::Left => (dy, -dx),
+    };
+    Rect::from_center(
+        Point::new(centre.x() + dx, centre.y() + dy),
+        rect.width(),
+        rect.height(),
+    )
+}
+
+/// Width and height of an image of `size` once rotated by `rot`
+fn rotated_size(size: (u32, u32), rot: &RotAngle) -> (u32, u32) {
+    if rot.is_sideways() {
+        (size.1, size.0)
+    } else {
+        size
+    }
+}
+
+/// Fraction of the pan range covered by panning `PAN_PIXELS` along one axis
+fn pan_step(src: u32, dst: u32, scale: f32) -> f32 {
+    let diff = (dst as f32 - (src as f32 * scale)) / 2.0;
+    (PAN_PIXELS / diff).abs()
 }
 
 /// Compute increment of skips
@@ -883,3 +1290,118 @@ fn move_to_trash_win<S: AsRef<OsStr>>(file: S) -> Result<i32, i32> {
         Err(ret)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{make_dst, pan_step, rotate_about, rotated_size};
+    use crate::ui::{FitMode, RotAngle};
+    use sdl2::pixels::PixelFormatEnum;
+    use sdl2::rect::{Point, Rect};
+    use sdl2::render::TextureQuery;
+
+    const ERROR: f32 = 0.001;
+    const ANGLES: [RotAngle; 4] = [
+        RotAngle::Up,
+        RotAngle::Right,
+        RotAngle::Down,
+        RotAngle::Left,
+    ];
+
+    /// A portrait image in a landscape window
+    const IMAGE: (u32, u32) = (400, 1000);
+    const WINDOW: (u32, u32) = (800, 600);
+
+    fn query(size: (u32, u32)) -> TextureQuery {
+        TextureQuery {
+            format: PixelFormatEnum::RGBA8888,
+            access: sdl2::render::TextureAccess::Static,
+            width: size.0,
+            height: size.1,
+        }
+    }
+
+    /// The box the image covers on screen once `dst` is rotated about its centre
+    fn shown_box(dst: Rect, rot: &RotAngle) -> Rect {
+        let (width, height) = rotated_size((dst.width(), dst.height()), rot);
+        Rect::from_center(dst.center(), width, height)
+    }
+
+    #[test]
+    fn test_fit_scale_uses_rotated_size() {
+        for rot in ANGLES.iter() {
+            let expected = if rot.is_sideways() { 0.8 } else { 0.6 };
+            let scale = FitMode::Fit.scale(rotated_size(IMAGE, rot), WINDOW);
+            assert!((scale - expected).abs() < ERROR);
+        }
+    }
+
+    #[test]
+    fn test_fitted_image_is_centred_within_window_at_every_angle() {
+        let viewport = Rect::new(0, 0, WINDOW.0, WINDOW.1);
+        for rot in ANGLES.iter() {
+            let scale = FitMode::Fit.scale(rotated_size(IMAGE, rot), WINDOW);
+            let dst = make_dst(&query(IMAGE), &viewport, scale, 0.0, 0.0, rot);
+            // The texture is always drawn unrotated at its scaled size
+            let expected = if rot.is_sideways() {
+                (320, 800)
+            } else {
+                (240, 600)
+            };
+            assert_eq!((dst.width(), dst.height()), expected);
+            let shown = shown_box(dst, rot);
+            assert_eq!(shown.center(), Point::new(400, 300));
+            assert!(viewport.contains_rect(shown));
+        }
+    }
+
+    #[test]
+    fn test_full_pan_moves_rotated_image_to_window_edge() {
+        let viewport = Rect::new(0, 0, WINDOW.0, WINDOW.1);
+        for rot in ANGLES.iter() {
+            let dst = make_dst(&query(IMAGE), &viewport, 0.5, 1.0, -1.0, rot);
+            let shown = shown_box(dst, rot);
+            assert_eq!(shown.left(), 0);
+            assert_eq!(shown.bottom(), 600);
+        }
+    }
+
+    #[test]
+    fn test_pan_steps_use_rotated_size() {
+        for rot in ANGLES.iter() {
+            let (width, height) = rotated_size(IMAGE, rot);
+            let x_step = pan_step(width, WINDOW.0, 0.5);
+            let y_step = pan_step(height, WINDOW.1, 0.5);
+            // 50 pixels of the space left around the image at half size
+            let (x_expected, y_expected) = if rot.is_sideways() {
+                (50.0 / 150.0, 50.0 / 200.0)
+            } else {
+                (50.0 / 300.0, 50.0 / 50.0)
+            };
+            assert!((x_step - x_expected).abs() < ERROR);
+            assert!((y_step - y_expected).abs() < ERROR);
+        }
+    }
+
+    #[test]
+    fn test_rotating_spread_turns_pages_about_its_centre() {
+        let centre = Point::new(100, 50);
+        let left_page = Rect::new(0, 0, 100, 100);
+        let right_page = Rect::new(100, 0, 100, 100);
+
+        assert_eq!(rotate_about(left_page, centre, &RotAngle::Up), left_page);
+        // Turned clockwise the left page ends up on top
+        assert_eq!(
+            rotate_about(left_page, centre, &RotAngle::Right),
+            Rect::new(50, -50, 100, 100)
+        );
+        assert_eq!(
+            rotate_about(right_page, centre, &RotAngle::Right),
+            Rect::new(50, 50, 100, 100)
+        );
+        assert_eq!(rotate_about(left_page, centre, &RotAngle::Down), right_page);
+        assert_eq!(
+            rotate_about(left_page, centre, &RotAngle::Left),
+            Rect::new(50, 50, 100, 100)
+        );
+    }
+}
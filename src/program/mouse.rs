@@ -0,0 +1,165 @@
+//! File that contains mouse functionality, zooming toward the cursor with the scroll wheel and
+//! panning by dragging the image
+use super::compare::compare_panes;
+use super::Program;
+use crate::ui::{Compare, ZoomAction};
+use sdl2::rect::Rect;
+
+impl<'a> Program<'a> {
+    /// Zooms in or out `times` steps keeping the point of the image under the cursor in place
+    /// `x` and `y` are the position of the cursor in the window
+    pub(super) fn zoom_at(
+        &mut self,
+        zoom: &ZoomAction,
+        times: usize,
+        x: i32,
+        y: i32,
+    ) -> Result<(), String> {
+        let (width, height) = match self.displayed_size() {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+        let area = self.area_under_cursor(x);
+        let old_scale = self.ui_state.scale;
+        match zoom {
+            ZoomAction::In => self.ui_state.zoom_in(times),
+            ZoomAction::Out => self.ui_state.zoom_out(times),
+        }
+        let new_scale = self.ui_state.scale;
+        self.ui_state.pan_x = anchored_pan(
+            (x - area.x()) as f32,
+            area.width() as f32,
+            width as f32,
+            old_scale,
+            new_scale,
+            self.ui_state.pan_x,
+        );
+        self.ui_state.pan_y = anchored_pan(
+            (y - area.y()) as f32,
+            area.height() as f32,
+            height as f32,
+            old_scale,
+            new_scale,
+            self.ui_state.pan_y,
+        );
+        self.render_screen(false)
+    }
+
+    /// Pans the image so it follows the cursor as it is dragged by `dx` and `dy` pixels
+    pub(super) fn drag(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+        let (width, height) = match self.displayed_size() {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+        let area = self.image_area();
+        let scale = self.ui_state.scale;
+        self.ui_state.pan_x = dragged_pan(
+            dx as f32,
+            area.width() as f32,
+            width as f32,
+            scale,
+            self.ui_state.pan_x,
+        );
+        self.ui_state.pan_y = dragged_pan(
+            dy as f32,
+            area.height() as f32,
+            height as f32,
+            scale,
+            self.ui_state.pan_y,
+        );
+        self.render_screen(false)
+    }
+
+    /// Area of the image the cursor is over, when comparing side by side this is the pane of the
+    /// pinned image if the cursor is left of the current image
+    fn area_under_cursor(&self, x: i32) -> Rect {
+        let area = self.image_area();
+        match self.ui_state.compare {
+            Some(Compare { flicker: None, .. }) if x < area.x() => {
+                compare_panes(self.screen.canvas.viewport()).0
+            }
+            _ => area,
+        }
+    }
+}
+
+/// Position of the near edge of the image along one axis, the same position `make_dst` places it
+fn image_offset(area: f32, content: f32, scale: f32, pan: f32) -> f32 {
+    let diff = (area - content * scale) / 2.0;
+    diff - diff * pan
+}
+
+/// Pan along one axis that places the near edge of the image at `offset`
+/// The pan is limited to the range keyboard panning allows
+fn pan_for_offset(offset: f32, area: f32, content: f32, scale: f32) -> f32 {
+    let diff = (area - content * scale) / 2.0;
+    // The image exactly fills the area so there is nothing to pan
+    if diff.abs() < f32::EPSILON {
+        return 0.0;
+    }
+    (1.0 - offset / diff).clamp(-1.0, 1.0)
+}
+
+/// Pan along one axis after zooming from `old_scale` to `new_scale`, such that the point of the
+/// image at `cursor` stays under the cursor
+/// `cursor` is relative to the start of the area the image is placed in
+fn anchored_pan(
+    cursor: f32,
+    area: f32,
+    content: f32,
+    old_scale: f32,
+    new_scale: f32,
+    pan: f32,
+) -> f32 {
+    let offset = image_offset(area, content, old_scale, pan);
+    // Point of the image at scale 1.0 that is under the cursor
+    let image_point = (cursor - offset) / old_scale;
+    pan_for_offset(cursor - image_point * new_scale, area, content, new_scale)
+}
+
+/// Pan along one axis after dragging the image `delta` pixels
+fn dragged_pan(delta: f32, area: f32, content: f32, scale: f32, pan: f32) -> f32 {
+    let offset = image_offset(area, content, scale, pan);
+    pan_for_offset(offset + delta, area, content, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{anchored_pan, dragged_pan, image_offset};
+
+    const ERROR: f32 = 0.001;
+
+    #[test]
+    fn test_zooming_keeps_point_under_cursor_in_place() {
+        let (area, content, cursor) = (800.0, 1000.0, 600.0);
+        let (old_scale, new_scale, pan) = (1.0, 1.1, 0.2);
+        let before = (cursor - image_offset(area, content, old_scale, pan)) / old_scale;
+
+        let new_pan = anchored_pan(cursor, area, content, old_scale, new_scale, pan);
+        let after = (cursor - image_offset(area, content, new_scale, new_pan)) / new_scale;
+        assert!((before - after).abs() < ERROR);
+    }
+
+    #[test]
+    fn test_zooming_at_centre_of_centred_image_keeps_it_centred() {
+        let pan = anchored_pan(400.0, 800.0, 400.0, 1.0, 1.5, 0.0);
+        assert!(pan.abs() < ERROR);
+    }
+
+    #[test]
+    fn test_zooming_toward_far_corner_is_limited_to_pan_range() {
+        // Zooming out of a large image toward its edge would move it off screen
+        let pan = anchored_pan(0.0, 800.0, 4000.0, 1.0, 0.1, -1.0);
+        assert!((-1.0..=1.0).contains(&pan));
+    }
+
+    #[test]
+    fn test_dragging_moves_image_with_cursor_within_pan_range() {
+        let pan = dragged_pan(50.0, 800.0, 1000.0, 1.0, 0.0);
+        assert!((pan - 0.5).abs() < ERROR);
+        assert!((image_offset(800.0, 1000.0, 1.0, pan) - -50.0).abs() < ERROR);
+
+        let pan = dragged_pan(500.0, 800.0, 1000.0, 1.0, 0.0);
+        assert!((pan - 1.0).abs() < ERROR);
+    }
+}
@@ -0,0 +1,192 @@
+//! File that contains remote control functionality, commands received on the control socket are
+//! run and answered with the resulting status of the program
+use super::{CompleteType, Program};
+use crate::ipc::Reply;
+use crate::ui::{Action, Mode, PanAction, ProcessAction, RotationDirection, ZoomAction};
+
+impl<'a> Program<'a> {
+    /// Runs every command received on the control socket since the last call, replying to each
+    /// with the status of the program after it ran
+    ///
+    /// Break is returned if a command left normal mode, for instance to display a message
+    pub(super) fn process_remote_commands(&mut self) -> Result<CompleteType, String> {
+        let requests = match self.ipc.as_mut() {
+            Some(ipc) => ipc.poll(),
+            None => return Ok(CompleteType::Complete),
+        };
+        if requests.is_empty() {
+            return Ok(CompleteType::Complete);
+        }
+
+        let mut next_step = CompleteType::Complete;
+        for request in requests {
+            // Each reply should only report on its own command
+            self.ui_state.mode = Mode::Normal;
+            self.remote = true;
+            let result = self.run_remote_command(&request.command);
+            self.remote = false;
+            result?;
+            // Remote commands can't be corrected interactively, so report them as errors instead
+            if let Mode::Command(msg) = &self.ui_state.mode {
+                self.ui_state.mode = Mode::Error(msg.clone());
+            }
+            let reply = self.remote_reply();
+            if let Some(ipc) = self.ipc.as_mut() {
+                ipc.reply(&request, &reply);
+            }
+            match self.ui_state.mode {
+                Mode::Normal => {}
+                Mode::Exit => return Ok(CompleteType::Break),
+                _ => next_step = CompleteType::Break,
+            }
+        }
+
+        if let CompleteType::Complete = next_step {
+            self.render_screen(false)?;
+        }
+        Ok(next_step)
+    }
+
+    /// Runs a single remote command
+    /// Navigation actions such as `next 5` or `goto 20` are handled here, and the command mode
+    /// commands that only change which images are viewed are run as they would be typed
+    fn run_remote_command(&mut self, command: &str) -> Result<(), String> {
+        // Allow commands to be written as they would be typed in command mode
+        let command = command.trim_start_matches(':').trim();
+        if command.is_empty() || command == "status" {
+            return Ok(());
+        }
+        let mut split = command.splitn(2, ' ');
+        // splitn always returns at least one item
+        let name = split.next().unwrap();
+        let argument = split.next().unwrap_or("").trim();
+
+        if name == "goto" {
+            match argument.parse::<usize>() {
+                Ok(number) if number > 0 => {
+                    self.jump_to_image_index(number - 1)?;
+                }
+                _ => {
+                    self.ui_state.mode = Mode::Error(format!(
+                        "\"goto\" requires an image number, got \"{}\"",
+                        argument
+                    ));
+                }
+            }
+            return Ok(());
+        }
+
+        let action = match remote_action(name) {
+            Some(action) => action,
+            None if is_remote_command(name) => return self.execute_command(command.to_string()),
+            None => {
+                self.ui_state.mode =
+                    Mode::Error(format!("\"{}\" can't be run by remote control", name));
+                return Ok(());
+            }
+        };
+        let mut times = 1;
+        let mut force = false;
+        for word in argument.split_whitespace() {
+            if word == "force" {
+                force = true;
+                continue;
+            }
+            match word.parse::<usize>() {
+                Ok(count) => times = count,
+                Err(_) => {
+                    self.ui_state.mode =
+                        Mode::Error(format!("\"{}\" is not a positive integer", word));
+                    return Ok(());
+                }
+            }
+        }
+        // No one may be at the window to answer, so actions that would be asked about have to
+        // be forced instead
+        if !force {
+            let count = self.affected_images(times);
+            if let Some(question) = self.confirm.question(&action, count) {
+                self.ui_state.mode = Mode::Error(format!(
+                    "{} needs to be confirmed, send \"{} {} force\" to go ahead",
+                    question.trim_end_matches("? y/N"),
+                    name,
+                    times
+                ));
+                return Ok(());
+            }
+        }
+        self.ui_state.confirmed = true;
+        self.dispatch_normal(ProcessAction::from((action, times)))?;
+        Ok(())
+    }
+
+    /// Status of the program sent back to remote control clients
+    fn remote_reply(&self) -> Reply {
+        let (ok, message) = match &self.ui_state.mode {
+            Mode::Error(msg) => (false, msg.clone()),
+            Mode::Success(msg) => (true, msg.clone()),
+            Mode::Exit => (true, "Exiting".to_string()),
+            _ => (true, String::new()),
+        };
+        Reply {
+            ok,
+            message,
+            // Counted from 1 like `goto` and the infobar
+            index: self.paths.index().map(|index| index + 1),
+            total: self.paths.max_viewable().unwrap_or(0),
+            path: self
+                .paths
+                .current_image_path()
+                .map(|path| path.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// Whether the command mode command can be run remotely, commands that run programs, change
+/// files or rebind keys are only run from the window
+fn is_remote_command(name: &str) -> bool {
+    matches!(
+        name,
+        "ng" | "newglob" | "sort" | "df" | "destfolder" | "m" | "max"
+    )
+}
+
+/// Normal mode actions that can be performed remotely, by name
+fn remote_action(name: &str) -> Option<Action<'static>> {
+    let action = match name {
+        "next" => Action::Next,
+        "prev" => Action::Prev,
+        "first" => Action::First,
+        "last" => Action::Last,
+        "forward" => Action::SkipForward,
+        "back" => Action::SkipBack,
+        "zoomin" => Action::Zoom(ZoomAction::In),
+        "zoomout" => Action::Zoom(ZoomAction::Out),
+        "rotate" => Action::Rotate(RotationDirection::Clockwise),
+        "rotateccw" => Action::Rotate(RotationDirection::CounterClockwise),
+        "panleft" => Action::Pan(PanAction::Left),
+        "panright" => Action::Pan(PanAction::Right),
+        "panup" => Action::Pan(PanAction::Up),
+        "pandown" => Action::Pan(PanAction::Down),
+        "fliph" => Action::FlipHorizontal,
+        "flipv" => Action::FlipVertical,
+        "fit" => Action::ToggleFit,
+        "center" => Action::CenterImage,
+        "fullscreen" => Action::ToggleFullscreen,
+        "mark" => Action::ToggleMark,
+        "spread" => Action::ToggleSpread,
+        "spreaddir" => Action::SwapSpreadDirection,
+        "compare" => Action::ToggleCompare,
+        "swapcompare" => Action::SwapCompare,
+        "flicker" => Action::ToggleFlicker,
+        "copy" => Action::Copy,
+        "move" => Action::Move,
+        "hardlink" => Action::Hardlink,
+        "symlink" => Action::Symlink,
+        "trash" => Action::Trash,
+        "delete" => Action::Delete,
+        "cancel" => Action::CancelTransfer,
+        _ => return None,
+    };
+    Some(action)
+}
@@ -0,0 +1,270 @@
+//! File that contains functionality that renames images with a filename template, previewing the
+//! new names until the renames are confirmed
+use super::Program;
+use crate::template::{self, Fields};
+use crate::ui::{self, ConfirmAction, Mode};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Most renames listed in the preview, the rest are only counted
+const PREVIEW_LINES: usize = 20;
+
+impl<'a> Program<'a> {
+    /// Works out the new names of the current image and (n-1) next images when a count was typed,
+    /// otherwise of the marked images or the current image, and previews them in rename mode
+    pub(super) fn start_rename(&mut self, template: &str, count: usize) {
+        match self.plan_renames(template, count) {
+            Ok(renames) if renames.is_empty() => {
+                self.ui_state.mode =
+                    Mode::Success("the images already have those names".to_string());
+                self.ui_state.rerender_time = Some(Instant::now());
+            }
+            Ok(renames) => {
+                self.ui_state.rename = Some(renames);
+                self.ui_state.mode = Mode::Rename;
+            }
+            Err(e) => self.ui_state.mode = Mode::Error(e),
+        }
+    }
+
+    /// The renames to make, checked against each other and the files already there
+    fn plan_renames(
+        &self,
+        template: &str,
+        count: usize,
+    ) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+        self.reject_dry_run("renaming")?;
+        self.reject_while_transferring()?;
+        self.reject_in_trash("renamed")?;
+        if template.is_empty() {
+            return Err(
+                "Command \":rename\" requires a template, such as {n:04}_{name}.{ext}".to_string(),
+            );
+        }
+        let images = self.selected_images(count)?;
+        if images.iter().any(|path| crate::archive::is_entry(path)) {
+            return Err("images inside archives can't be renamed".to_string());
+        }
+        let renames = renamed_paths(&images, template)?;
+        match find_collision(&renames, |path| path.exists()) {
+            Some(collision) => Err(collision),
+            None => Ok(renames),
+        }
+    }
+
+    /// Rename mode shows the renames until they are confirmed or cancelled
+    pub(super) fn run_rename_mode(&mut self) -> Result<(), String> {
+        self.render_screen(false)?;
+        self.run_modal(|program, event| {
+            match ui::process_confirm_mode(event) {
+                ConfirmAction::Confirm => program.finish_rename(),
+                ConfirmAction::Cancel => program.ui_state.mode = Mode::Normal,
+                ConfirmAction::Quit => program.ui_state.mode = Mode::Exit,
+                ConfirmAction::ReRender => {
+                    program.render_screen(false)?;
+                    return Ok(None);
+                }
+                ConfirmAction::Noop => return Ok(None),
+            }
+            Ok(Some(()))
+        })?;
+        self.ui_state.rename = None;
+        Ok(())
+    }
+
+    /// Renames the previewed images and tracks them by their new paths, reporting how it went
+    fn finish_rename(&mut self) {
+        let renames = self.ui_state.rename.take().unwrap_or_default();
+        let (renamed, failures) = rename_files(&renames);
+        for (old, new) in &renamed {
+            self.journal.ok("rename", old, Some(new));
+            self.paths.rename_image(old, new.clone());
+        }
+        for (old, new, e) in &failures {
+            eprintln!("Failed to rename {}: {}", old.display(), e);
+            self.journal.failed("rename", old, Some(new), e);
+        }
+        if failures.is_empty() {
+            self.ui_state.mode = Mode::Success(format!("renamed {} image(s)", renamed.len()));
+            self.ui_state.rerender_time = Some(Instant::now());
+        } else {
+            self.ui_state.mode = Mode::Error(format!(
+                "Failed to rename {} of {} images: {}: {}",
+                failures.len(),
+                renames.len(),
+                failures[0].0.display(),
+                failures[0].2
+            ));
+        }
+    }
+
+    /// Lists the previewed renames in the middle of the window
+    pub(super) fn render_rename_preview(&mut self) -> Result<(), String> {
+        let lines = match &self.ui_state.rename {
+            Some(renames) => preview_lines(renames),
+            None => return Ok(()),
+        };
+        self.render_text_box(&lines)
+    }
+}
+
+/// Each image paired with the path the template names it, images the template doesn't change
+/// are left out
+fn renamed_paths(images: &[PathBuf], template: &str) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut renames = Vec::new();
+    for (i, path) in images.iter().enumerate() {
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+        let fields = Fields {
+            path,
+            n: i + 1,
+            ext: &ext,
+        };
+        let new = path.with_file_name(template::expand(template, &fields)?);
+        if &new != path {
+            renames.push((path.clone(), new));
+        }
+    }
+    Ok(renames)
+}
+
+/// Describes the first rename that would overwrite a file, either one that `exists` and isn't
+/// being renamed itself, or the new name of another image
+fn find_collision<F>(renames: &[(PathBuf, PathBuf)], exists: F) -> Option<String>
+where
+    F: Fn(&Path) -> bool,
+{
+    let renamed: HashSet<&PathBuf> = renames.iter().map(|(old, _)| old).collect();
+    let mut taken = HashSet::new();
+    for (old, new) in renames {
+        if !taken.insert(new) {
+            return Some(format!(
+                "more than one image would be renamed to {}",
+                new.display()
+            ));
+        }
+        if exists(new) && !renamed.contains(new) {
+            return Some(format!(
+                "renaming {} would overwrite {}",
+                old.display(),
+                new.display()
+            ));
+        }
+    }
+    None
+}
+
+/// Renames the files, returning the renames that were made and the others along with why
+/// they failed
+/// Every file is first moved to a temporary name so images can swap or shift names
+#[allow(clippy::type_complexity)]
+fn rename_files(
+    renames: &[(PathBuf, PathBuf)],
+) -> (Vec<(PathBuf, PathBuf)>, Vec<(PathBuf, PathBuf, String)>) {
+    let mut failures = Vec::new();
+    let mut staged = Vec::new();
+    for (old, new) in renames {
+        let name = old.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = old.with_file_name(format!(".{}.riv-rename", name));
+        match fs::rename(old, &tmp) {
+            Ok(_) => staged.push((old, tmp, new)),
+            Err(e) => failures.push((old.clone(), new.clone(), e.to_string())),
+        }
+    }
+    let mut renamed = Vec::new();
+    for (old, tmp, new) in staged {
+        match fs::rename(&tmp, new) {
+            Ok(_) => renamed.push((old.clone(), new.clone())),
+            Err(e) => {
+                // Put the image back under its old name
+                let _ = fs::rename(&tmp, old);
+                failures.push((old.clone(), new.clone(), e.to_string()));
+            }
+        }
+    }
+    (renamed, failures)
+}
+
+/// Lines of the preview, the old and new name of each image
+fn preview_lines(renames: &[(PathBuf, PathBuf)]) -> Vec<String> {
+    let name = |path: &PathBuf| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let mut lines: Vec<String> = renames
+        .iter()
+        .take(PREVIEW_LINES)
+        .map(|(old, new)| format!(" {} -> {} ", name(old), name(new)))
+        .collect();
+    if renames.len() > PREVIEW_LINES {
+        lines.push(format!(" ... and {} more ", renames.len() - PREVIEW_LINES));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_collision, rename_files, renamed_paths};
+    use crate::test_dir::TestDir;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| Path::new("/photos").join(name))
+            .collect()
+    }
+
+    #[test]
+    fn test_images_are_renamed_in_order_and_unchanged_names_skipped() {
+        let images = paths(&["b.png", "0002.jpg", "a.png"]);
+        let renames = renamed_paths(&images, "{n:04}.{ext}").unwrap();
+        assert_eq!(
+            renames,
+            vec![
+                (images[0].clone(), PathBuf::from("/photos/0001.png")),
+                (images[2].clone(), PathBuf::from("/photos/0003.png")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collisions_are_found() {
+        let images = paths(&["a.png", "b.png"]);
+        // Both images get the same name
+        let renames = renamed_paths(&images, "same.{ext}").unwrap();
+        assert!(find_collision(&renames, |_| false).is_some());
+
+        // Another file already has the name
+        let renames = renamed_paths(&images[..1], "c.{ext}").unwrap();
+        assert!(find_collision(&renames, |_| false).is_none());
+        assert!(find_collision(&renames, |_| true).is_some());
+
+        // Names of images being renamed themselves are free to take
+        let renames = vec![
+            (images[0].clone(), images[1].clone()),
+            (images[1].clone(), images[0].clone()),
+        ];
+        assert!(find_collision(&renames, |_| true).is_none());
+    }
+
+    #[test]
+    fn test_images_can_swap_names() {
+        let dir = TestDir::new("rename");
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let renames = vec![(a.clone(), b.clone()), (b.clone(), a.clone())];
+        let (renamed, failures) = rename_files(&renames);
+
+        assert!(failures.is_empty());
+        assert_eq!(renamed, renames);
+        assert_eq!(fs::read(&a).unwrap(), b"b");
+        assert_eq!(fs::read(&b).unwrap(), b"a");
+    }
+}
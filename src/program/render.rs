@@ -1,10 +1,16 @@
 use crate::infobar;
-use crate::program::{make_dst, Program};
+use crate::orientation::Orientation;
+use crate::program::compare::compare_panes;
+use crate::program::spread::{matched_width, split_spread};
+use crate::program::{make_dst, rotate_about, rotated_size, Program};
 use crate::ui::{HelpRender, Mode, RotAngle};
-use sdl2::image::LoadTexture;
+use sdl2::image::{ImageRWops, LoadTexture};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use sdl2::render::BlendMode;
+use sdl2::render::{BlendMode, Texture, TextureCreator, TextureQuery};
+use sdl2::rwops::RWops;
+use sdl2::video::WindowContext;
+use std::path::Path;
 
 const PADDING: i32 = 30;
 const HALF_PAD: i32 = 15;
@@ -27,6 +33,8 @@ impl<'a> Program<'a> {
         }
         self.screen.canvas.clear();
         self.render_image(force_render)?;
+        self.render_crop_selection()?;
+        self.render_rename_preview()?;
         if self.ui_state.render_infobar {
             self.render_infobar()?;
         }
@@ -39,39 +47,125 @@ impl<'a> Program<'a> {
 
     fn render_image(&mut self, force_render: bool) -> Result<(), String> {
         self.set_image_texture(force_render)?;
-        match self.screen.last_texture {
-            Some(_) => (),
+        let tex = match self.screen.last_texture.as_ref() {
+            Some(tex) => tex,
             None => return Ok(()),
         };
-        let tex = self.screen.last_texture.as_ref().unwrap();
-        let query = tex.query();
-        // Area to render other rectangle on
-        let target = self.screen.canvas.viewport();
-        let dst = make_dst(
-            &query,
-            &target,
+        let (scale, pan_x, pan_y, rot) = (
             self.ui_state.scale,
             self.ui_state.pan_x,
             self.ui_state.pan_y,
+            &self.ui_state.rot_angle,
         );
-
-        let angle = match self.ui_state.rot_angle {
-            RotAngle::Up => 0.0,
-            RotAngle::Right => 90.0,
-            RotAngle::Down => 180.0,
-            RotAngle::Left => 270.0,
-        };
-        if let Err(e) = self.screen.canvas.copy_ex(
-            &tex,
-            None,
-            dst,
-            angle,
-            None,
-            self.ui_state.flip_horizontal,
-            self.ui_state.flip_vertical,
+        let viewport = self.screen.canvas.viewport();
+        let orientation = self.screen.last_orientation;
+        let upright = upright_query(tex, orientation);
+
+        // Each image to draw, the orientation its file says to show it in, where it is drawn
+        // before the view is turned, and the area it is kept within
+        let mut images = Vec::with_capacity(2);
+        match (
+            self.ui_state.compare.as_ref(),
+            self.screen.pinned_texture.as_ref(),
         ) {
-            eprintln!("Failed to copy image to screen {}", e);
+            (Some(compare), Some(pinned)) => {
+                let pinned_orientation = self.screen.pinned_orientation;
+                let pinned_upright = upright_query(pinned, pinned_orientation);
+                match compare.flicker {
+                    None => {
+                        let (left, right) = compare_panes(viewport);
+                        images.push((
+                            pinned,
+                            pinned_orientation,
+                            place(&pinned_upright, left, scale, pan_x, pan_y, rot),
+                            left,
+                        ));
+                        images.push((
+                            tex,
+                            orientation,
+                            place(&upright, right, scale, pan_x, pan_y, rot),
+                            right,
+                        ));
+                    }
+                    Some(_) => {
+                        let (shown, shown_orientation, query) = if compare.showing_pinned {
+                            (pinned, pinned_orientation, pinned_upright)
+                        } else {
+                            (tex, orientation, upright)
+                        };
+                        let dst = place(&query, viewport, scale, pan_x, pan_y, rot);
+                        images.push((shown, shown_orientation, dst, viewport));
+                    }
+                }
+            }
+            _ => {
+                // Both images of a spread are placed as if they were a single image
+                let (width, height) = self.content_size().unwrap();
+                let query = TextureQuery {
+                    width,
+                    height,
+                    ..upright
+                };
+                let dst = place(&query, viewport, scale, pan_x, pan_y, rot);
+                match self.screen.second_texture.as_ref() {
+                    Some(second) => {
+                        let second_orientation = self.screen.second_orientation;
+                        let second_query = upright_query(second, second_orientation);
+                        let second_width = matched_width(
+                            (upright.width, upright.height),
+                            (second_query.width, second_query.height),
+                        );
+                        let (first_dst, second_dst) = split_spread(
+                            dst,
+                            upright.width,
+                            second_width,
+                            self.ui_state.spread_direction,
+                        );
+                        // Each page is rotated on its own, so turn the spread as a whole
+                        let centre = dst.center();
+                        images.push((
+                            tex,
+                            orientation,
+                            rotate_about(first_dst, centre, rot),
+                            viewport,
+                        ));
+                        images.push((
+                            second,
+                            second_orientation,
+                            rotate_about(second_dst, centre, rot),
+                            viewport,
+                        ));
+                    }
+                    None => images.push((tex, orientation, dst, viewport)),
+                }
+            }
         }
+
+        let turn = self.view_turn();
+        for (texture, orientation, dst, area) in images {
+            // The view is turned and flipped on top of the way each file says to show its image
+            let shown = orientation.then(turn);
+            let angle = match shown.rotation {
+                RotAngle::Up => 0.0,
+                RotAngle::Right => 90.0,
+                RotAngle::Down => 180.0,
+                RotAngle::Left => 270.0,
+            };
+            // Keep zoomed in images from spilling into the other pane
+            self.screen.canvas.set_clip_rect(area);
+            if let Err(e) = self.screen.canvas.copy_ex(
+                texture,
+                None,
+                texture_dst(dst, orientation),
+                angle,
+                None,
+                shown.mirrored,
+                false,
+            ) {
+                eprintln!("Failed to copy image to screen {}", e);
+            }
+        }
+        self.screen.canvas.set_clip_rect(None);
         Ok(())
     }
 
@@ -85,17 +179,13 @@ impl<'a> Program<'a> {
             return Ok(());
         }
 
-        //reset horizontal and vertical flip on new image load
-        self.ui_state.flip_horizontal = false;
-        self.ui_state.flip_vertical = false;
-
         let current_imagepath = match self.paths.current_image_path() {
             Some(path) => path,
             // No images were found, so no image to load
             None => return Ok(()),
         };
 
-        let texture = match self.screen.texture_creator.load_texture(current_imagepath) {
+        let texture = match load_image_texture(self.screen.texture_creator, current_imagepath) {
             Ok(t) => {
                 self.screen.last_index = self.paths.index();
                 t
@@ -106,16 +196,24 @@ impl<'a> Program<'a> {
             }
         };
 
-        // Set the default state for viewing of the image
+        // Set the default state for viewing of the image, turned the way the file says to show it
+        self.screen.last_orientation = crate::orientation::read_orientation(current_imagepath);
+        self.ui_state.rot_angle = RotAngle::Up;
+        self.ui_state.flip_horizontal = false;
+        self.ui_state.flip_vertical = false;
         self.screen.last_texture = Some(texture);
+        self.load_spread_page();
         self.screen.dirty = false;
-        // fit to screen
-        self.ui_state.scale = self.calculate_scale_for_fit();
-        // no offsets
-        self.ui_state.pan_x = 0.0;
-        self.ui_state.pan_y = 0.0;
-        // 0 degree rotation
-        self.ui_state.rot_angle = RotAngle::Up;
+        if !self.ui_state.keep_view {
+            self.ui_state.fit_mode = Some(self.ui_state.default_fit);
+            // no offsets
+            self.ui_state.pan_x = 0.0;
+            self.ui_state.pan_y = 0.0;
+        }
+        // fit to screen, unless keeping a zoom set by hand
+        if self.ui_state.fit_mode.is_some() {
+            self.ui_state.scale = self.calculate_scale_for_fit();
+        }
         Ok(())
     }
 
@@ -198,23 +296,26 @@ impl<'a> Program<'a> {
             HelpRender::Normal => normal_help_text(),
             HelpRender::Command => command_help_text(),
         };
+        self.render_text_box(&text)
+    }
+
+    /// Draws the lines of text in the middle of the window on a box as wide as the longest line
+    pub(super) fn render_text_box<S: AsRef<str>>(&mut self, text: &[S]) -> Result<(), String> {
+        if text.is_empty() {
+            return Ok(());
+        }
         let total_height = LINE_HEIGHT * text.len() as i32 + LINE_PADDING * (text.len() as i32 - 1);
         let mut y = (self.screen.canvas.viewport().height() as f32 / 2.0
             - total_height as f32 / 2.0) as i32;
-        let w = {
-            let surface = self
+        let mut w = 0;
+        for line in text {
+            let (width, _) = self
                 .screen
                 .mono_font
-                .render(text[0])
-                .blended(dark_text_color())
-                .map_err(|e| e.to_string())?;
-            let texture = self
-                .screen
-                .texture_creator
-                .create_texture_from_surface(&surface)
+                .size_of(line.as_ref())
                 .map_err(|e| e.to_string())?;
-            texture.query().width
-        };
+            w = w.max(width);
+        }
         // Draw the Box
         let dims = (total_height as u32, w);
         self.render_help_box(dims)?;
@@ -224,7 +325,7 @@ impl<'a> Program<'a> {
             let surface = self
                 .screen
                 .mono_font
-                .render(line)
+                .render(line.as_ref())
                 .blended(dark_text_color())
                 .map_err(|e| e.to_string())?;
             let texture = self
@@ -298,6 +399,62 @@ impl<'a> Program<'a> {
     }
 }
 
+/// Places an image of the queried size within `area` at the given scale, pan and rotation
+pub(super) fn place(
+    query: &TextureQuery,
+    area: Rect,
+    scale: f32,
+    pan_x: f32,
+    pan_y: f32,
+    rot: &RotAngle,
+) -> Rect {
+    let dst = make_dst(query, &area, scale, pan_x, pan_y, rot);
+    Rect::new(
+        area.x() + dst.x(),
+        area.y() + dst.y(),
+        dst.width(),
+        dst.height(),
+    )
+}
+
+/// Size of the texture once turned the way its file says to show it
+pub(super) fn upright_query(texture: &Texture, orientation: Orientation) -> TextureQuery {
+    let query = texture.query();
+    let (width, height) = rotated_size((query.width, query.height), &orientation.rotation);
+    TextureQuery {
+        width,
+        height,
+        ..query
+    }
+}
+
+/// Where to draw a texture so that it covers `dst` once turned the way its file says to show it
+/// Textures are turned about their centre, so one on its side has its width and height swapped
+pub(super) fn texture_dst(dst: Rect, orientation: Orientation) -> Rect {
+    if orientation.rotation.is_sideways() {
+        Rect::from_center(dst.center(), dst.height(), dst.width())
+    } else {
+        dst
+    }
+}
+
+/// Loads the image at path into a texture
+/// Images inside archives are decoded from memory as they have no file of their own
+pub(super) fn load_image_texture<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    path: &Path,
+) -> Result<Texture<'a>, String> {
+    if !crate::archive::is_entry(path) {
+        return texture_creator.load_texture(path);
+    }
+    let bytes = crate::archive::read_entry(path)?;
+    let rwops = RWops::from_bytes(&bytes)?;
+    let surface = rwops.load()?;
+    texture_creator
+        .create_texture_from_surface(&surface)
+        .map_err(|e| e.to_string())
+}
+
 fn mode_colors(m: &Mode) -> Colors {
     match m {
         Mode::Normal | Mode::MultiNormal => Colors {
@@ -315,11 +472,13 @@ fn mode_colors(m: &Mode) -> Colors {
             secondary: green(),
             tertiary: grey(),
         },
-        Mode::Command(_) => Colors {
-            primary: light_yellow(),
-            secondary: yellow(),
-            tertiary: grey(),
-        },
+        Mode::Command(_) | Mode::Crop | Mode::Rename | Mode::Conflict(_) | Mode::Confirm(_) => {
+            Colors {
+                primary: light_yellow(),
+                secondary: yellow(),
+                tertiary: grey(),
+            }
+        }
         Mode::Exit => Colors {
             primary: light_blue(),
             secondary: blue(),
@@ -330,9 +489,15 @@ fn mode_colors(m: &Mode) -> Colors {
 
 fn mode_text_color(m: &Mode) -> Color {
     match m {
-        Mode::Normal | Mode::MultiNormal | Mode::Exit | Mode::Command(_) | Mode::Success(_) => {
-            dark_text_color()
-        }
+        Mode::Normal
+        | Mode::MultiNormal
+        | Mode::Exit
+        | Mode::Command(_)
+        | Mode::Success(_)
+        | Mode::Crop
+        | Mode::Rename
+        | Mode::Conflict(_)
+        | Mode::Confirm(_) => dark_text_color(),
         Mode::Error(_) => light_text_color(),
     }
 }
@@ -397,46 +562,71 @@ fn normal_help_text() -> Vec<&'static str> {
         "| 0-9 (many) | Key1 of action to perform  | Perform the specified action many times             |",
         "| q          | Esc                        | Quit                                                |",
         "| k/j        | Left/Right                 | Previous/Next Image                                 |",
-        "| i/o        | Up/Down                    | Zoom in/out                                         |",
+        "| i/o        | Up/Down, Scroll Wheel      | Zoom in/out (toward the cursor when scrolling)      |",
         "| r/R        |                            | Rotate image clockwise/counterclockwise             |",
-        "| H, J, K, L | Shift + Up/Down/Left/Right | Pan left/down/up/right                              |",
+        "| H, J, K, L | Shift + Up/Down/Left/Right | Pan left/down/up/right, or drag with the mouse      |",
         "| h          |                            | Flip image horizontally                             |",
         "| v          |                            | Flip image vertically                               |",
         "| b/w        | PageDown/PageUp            | Backward/Forward 10% of images                      |",
         "| g/G        | Home/End                   | First/Last Image (55G jumps to the 55th image)      |",
         "| m          |                            | Move image to destination folder (default ./keep)   |",
         "| c          |                            | Copy image to destination folder (default ./keep)   |",
+        "| l          |                            | Hardlink image into destination folder              |",
+        "| p          |                            | Symlink image into destination folder               |",
         "| d          | Delete                     | Move image to OS specific trash location            |",
         "| D          | Shift + Delete             | Delete image from its location                      |",
         "| t          |                            | Toggle information bar                              |",
         "| f          | F11                        | Toggle fullscreen mode                              |",
         "| ?          |                            | Toggle help box                                     |",
-        "| z          | Left Click                 | Toggle actual size vs scaled image                  |",
+        "| z          | Double Click               | Toggle actual size vs scaled image                  |",
         "| Z          |                            | Center image                                        |",
+        "| F          |                            | Switch to the next fit mode                         |",
+        "| C          |                            | Crop image (drag to select, Enter saves a copy)     |",
         "| . (period) |                            | Repeat last action                                  |",
+        "| x          |                            | Toggle mark on image                                |",
+        "| X          |                            | Cancel moving, copying or linking images            |",
+        "| M + letter |                            | Bookmark image under the letter                     |",
+        "| ' + letter |                            | Jump to image bookmarked under the letter           |",
+        "| s          |                            | Toggle two page spread                              |",
+        "| S          |                            | Swap spread reading direction (LTR/RTL)             |",
+        "| a          |                            | Pin image to compare against / stop comparing       |",
+        "| A          |                            | Swap pinned and compared images                     |",
+        "| B          |                            | Toggle flicker between pinned and compared images   |",
         "+------------+----------------------------+-----------------------------------------------------+",
     ]
 }
 
 fn command_help_text() -> Vec<&'static str> {
     vec![
-        "+-------+------------+----------+-------------------------------------+",
-        "| Short | Long       | Argument | Action                              |",
-        "+-------+------------+----------+-------------------------------------+",
-        "| ng    | newglob    | Required | The new glob/directory/file         |",
-        "| ?     | help       | None     | Toggle help box                     |",
-        "| q     | quit       | None     | Quit                                |",
-        "|       | sort       | Optional | The method to sort by               |",
-        "| df    | destfolder | Required | New folder to move/copy images to   |",
-        "| m     | max        | Required | New maximum number of files to view |",
-        "+---------------------------------------------------------------------+",
-        "| Sorting Options                                                     |",
-        "+---------------------------------------------------------------------+",
-        "| Alphabetical                                                        |",
-        "| Date                                                                |",
-        "| Size                                                                |",
-        "| DepthFirst                                                          |",
-        "| BreadthFirst                                                        |",
-        "+---------------------------------------------------------------------+",
+        "+-------+-------------------+----------+-------------------------------------+",
+        "| Short | Long              | Argument | Action                              |",
+        "+-------+-------------------+----------+-------------------------------------+",
+        "| ng    | newglob           | Required | The new globs/directories/files     |",
+        "| ?     | help              | None     | Toggle help box                     |",
+        "| q     | quit              | None     | Quit                                |",
+        "|       | sort              | Optional | The method to sort by               |",
+        "| df    | destfolder        | Required | New folder to move/copy images to   |",
+        "| m     | max               | Required | New maximum number of files to view |",
+        "| !     |                   | Required | Shell command to run (%f, %F, %d)   |",
+        "|       | bind              | Required | Bind a key to a shell command       |",
+        "|       | unmark            | None     | Unmark all images                   |",
+        "|       | exclude           | Optional | Skip images matching a glob         |",
+        "|       | set               | Optional | View or change settings             |",
+        "|       | write-orientation | None     | Save rotation and flips to the file |",
+        "|       | export            | Required | Save converted copies to destfolder |",
+        "|       | rename            | Required | Rename images with a template       |",
+        "|       | trash             | Optional | View trashed images or go back      |",
+        "|       | restore           | None     | Restore images from the trash       |",
+        "|       | session           | Required | save or load, then an optional name |",
+        "|       | bookmark          | Optional | a name, then an optional folder     |",
+        "+----------------------------------------------------------------------------+",
+        "| Sorting Options                                                            |",
+        "+----------------------------------------------------------------------------+",
+        "| Alphabetical                                                               |",
+        "| Date                                                                       |",
+        "| Size                                                                       |",
+        "| DepthFirst                                                                 |",
+        "| BreadthFirst                                                               |",
+        "+----------------------------------------------------------------------------+",
     ]
 }
@@ -0,0 +1,107 @@
+//! File that contains functionality that saves the images being viewed and where they are being
+//! viewed from as a session, and views them again from a saved session
+use super::command_mode::glob_paths;
+use super::Program;
+use crate::session::{Session, DEFAULT_SESSION};
+use crate::sort::SortOrder;
+
+impl<'a> Program<'a> {
+    /// Runs `:session save [name]` or `:session load [name]`, the name defaulting to the session
+    /// being kept or `default`
+    pub(super) fn session_command(&mut self, arguments: &str) -> Result<String, String> {
+        let mut split = arguments.split_whitespace();
+        let action = split.next().unwrap_or("");
+        let name = match split.next() {
+            Some(name) => name.to_string(),
+            None => self
+                .session
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SESSION.to_string()),
+        };
+        if let Some(extra) = split.next() {
+            return Err(format!("Unexpected argument \"{}\" to \":session\"", extra));
+        }
+        match action {
+            "save" => {
+                self.current_session().save(&name)?;
+                let msg = format!("saved session {}", name);
+                self.session = Some(name);
+                Ok(msg)
+            }
+            "load" => self.load_session(name),
+            _ => Err("Command \":session\" requires save or load, then a name".to_string()),
+        }
+    }
+
+    /// The images being viewed and where they are being viewed from
+    /// While viewing the trash, the images viewed before it was opened are saved instead
+    fn current_session(&self) -> Session {
+        let paths = self.viewed_paths();
+        Session {
+            search_dir: self.search_dir.clone(),
+            globs: self.globs.clone(),
+            sort_order: self.sorter.order().to_string(),
+            reverse: self.sorter.reversed(),
+            max: paths.maximum(),
+            dest_folder: paths.dest_folder.clone(),
+            marks: paths.marks(),
+            current: paths.current_image_path().cloned(),
+        }
+    }
+
+    /// Searches the paths of the saved session again and views its images the way they were
+    /// viewed when it was saved
+    fn load_session(&mut self, name: String) -> Result<String, String> {
+        self.reject_while_transferring()?;
+        let session = Session::load(&name)?;
+        let sort_order: SortOrder = session.sort_order.parse()?;
+        let (images, base_dir) = glob_paths(&session.search_dir, &session.globs, &self.exclusions)?;
+
+        self.paths.reload_images(images);
+        // The session's images replace the trash, rather than being replaced when it is closed
+        self.trash_view = None;
+        self.paths.base_dir = base_dir;
+        self.paths.dest_folder = session.dest_folder.clone();
+        self.sorter.set_order(sort_order);
+        self.sorter.set_reverse(session.reverse);
+        self.sorter.sort(self.paths.images_as_mut_slice());
+        self.paths.set_actual_maximum(session.max);
+        self.paths.clear_marks();
+        self.restore_position(&session);
+        self.search_dir = session.search_dir;
+        self.globs = session.globs;
+        self.screen.dirty = true;
+
+        let msg = format!(
+            "loaded session {}, {} images",
+            name,
+            self.paths.images().len()
+        );
+        self.session = Some(name);
+        Ok(msg)
+    }
+
+    /// Marks the images marked in the session and goes back to its current image, if they are
+    /// still among the images
+    pub(super) fn restore_position(&mut self, session: &Session) {
+        for path in &session.marks {
+            self.paths.mark(path);
+        }
+        let index = session
+            .current
+            .as_ref()
+            .and_then(|current| self.paths.images().iter().position(|path| path == current));
+        if let Some(index) = index {
+            self.paths.set_index_safe(index);
+        }
+    }
+
+    /// Saves the session being kept, reporting rather than failing if it can't be saved
+    pub(super) fn save_session_on_exit(&self) {
+        if let Some(name) = &self.session {
+            if let Err(e) = self.current_session().save(name) {
+                eprintln!("Failed to save session {}: {}", name, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! File that contains two page spread functionality, the current image and the next image are
+//! shown side by side like the pages of a book for reading comics and manga
+use super::render::{load_image_texture, upright_query};
+use super::{rotated_size, Program};
+use crate::orientation::read_orientation;
+use crate::ui::{Mode, SpreadDirection};
+use sdl2::rect::Rect;
+use std::time::Instant;
+
+impl<'a> Program<'a> {
+    /// Toggles showing the current and next images side by side
+    pub(super) fn toggle_spread(&mut self) -> Result<(), String> {
+        self.ui_state.spread = !self.ui_state.spread;
+        // Only one image can be shown next to the current image
+        self.ui_state.compare = None;
+        self.screen.pinned_texture = None;
+        self.screen.dirty = true;
+        self.render_screen(false)
+    }
+
+    /// Swaps which side of the spread the current image is shown on
+    pub(super) fn swap_spread_direction(&mut self) -> Result<(), String> {
+        let (direction, msg) = match self.ui_state.spread_direction {
+            SpreadDirection::LeftToRight => (SpreadDirection::RightToLeft, "right to left"),
+            SpreadDirection::RightToLeft => (SpreadDirection::LeftToRight, "left to right"),
+        };
+        self.ui_state.spread_direction = direction;
+        self.ui_state.mode = Mode::Success(format!("reading spreads {}", msg));
+        self.ui_state.rerender_time = Some(Instant::now());
+        self.render_screen(false)
+    }
+
+    /// Moves forward by `times` spreads, a wide image or the last image counts as a spread
+    pub(super) fn spread_increment(&mut self, times: usize) {
+        for _ in 0..times {
+            let (index, last) = match (self.paths.index(), self.paths.max_viewable_index()) {
+                (Some(index), Some(last)) => (index, last),
+                _ => return,
+            };
+            let step = forward_step(index, last, &|i| self.is_wide_image(i));
+            self.paths.increment(step);
+        }
+    }
+
+    /// Moves back by `times` spreads, a wide image or the first image counts as a spread
+    pub(super) fn spread_decrement(&mut self, times: usize) {
+        for _ in 0..times {
+            let (index, last) = match (self.paths.index(), self.paths.max_viewable_index()) {
+                (Some(index), Some(last)) => (index, last),
+                _ => return,
+            };
+            let step = back_step(index, last, &|i| self.is_wide_image(i));
+            self.paths.decrement(step);
+        }
+    }
+
+    /// Loads the image after the current image to show beside it
+    /// Nothing is loaded when not in spread mode, at the last image, or if either image is wide
+    pub(super) fn load_spread_page(&mut self) {
+        self.screen.second_texture = None;
+        if !self.ui_state.spread {
+            return;
+        }
+        let (index, last) = match (self.paths.index(), self.paths.max_viewable_index()) {
+            (Some(index), Some(last)) => (index, last),
+            _ => return,
+        };
+        let first_wide = match self.screen.last_texture.as_ref() {
+            Some(tex) => {
+                let query = upright_query(tex, self.screen.last_orientation);
+                query.width > query.height
+            }
+            None => return,
+        };
+        if first_wide || index >= last {
+            return;
+        }
+        let path = self.paths.images()[index + 1].clone();
+        match load_image_texture(self.screen.texture_creator, &path) {
+            Ok(tex) => {
+                let orientation = read_orientation(&path);
+                let query = upright_query(&tex, orientation);
+                if query.width <= query.height {
+                    self.screen.second_texture = Some(tex);
+                    self.screen.second_orientation = orientation;
+                }
+            }
+            Err(e) => eprintln!("Failed to render image {}", e),
+        }
+    }
+
+    /// Whether the image at `index` is wider than it is tall, such as a spread scanned as one
+    /// image, which is always shown on its own
+    /// The image is turned the way its file says to show it
+    fn is_wide_image(&self, index: usize) -> bool {
+        let dimensions = self.paths.images().get(index).and_then(|path| {
+            crate::archive::image_dimensions(path)
+                .map(|size| rotated_size(size, &read_orientation(path).rotation))
+        });
+        matches!(dimensions, Some((width, height)) if width > height)
+    }
+}
+
+/// Whether the image at `index` is shown together with the image after it
+fn is_paired(index: usize, last: usize, wide: &dyn Fn(usize) -> bool) -> bool {
+    index < last && !wide(index) && !wide(index + 1)
+}
+
+/// Number of images to move forward to get past the spread starting at `index`
+fn forward_step(index: usize, last: usize, wide: &dyn Fn(usize) -> bool) -> usize {
+    if is_paired(index, last, wide) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Number of images to move back to get to the start of the spread before `index`
+fn back_step(index: usize, last: usize, wide: &dyn Fn(usize) -> bool) -> usize {
+    if index >= 2 && is_paired(index - 2, last, wide) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Splits the area of the whole spread into the areas of the current image and the second image
+/// `first_width` and `second_width` are the widths of the images relative to each other
+pub(super) fn split_spread(
+    dst: Rect,
+    first_width: u32,
+    second_width: u32,
+    direction: SpreadDirection,
+) -> (Rect, Rect) {
+    let fraction = first_width as f32 / (first_width + second_width) as f32;
+    let first_width = (dst.width() as f32 * fraction) as u32;
+    let second_width = dst.width().saturating_sub(first_width);
+    let (first_x, second_x) = match direction {
+        SpreadDirection::LeftToRight => (dst.x(), dst.x() + first_width as i32),
+        SpreadDirection::RightToLeft => (dst.x() + second_width as i32, dst.x()),
+    };
+    (
+        Rect::new(first_x, dst.y(), first_width, dst.height()),
+        Rect::new(second_x, dst.y(), second_width, dst.height()),
+    )
+}
+
+/// Width the second image is displayed at so that it has the same height as the first
+pub(super) fn matched_width(first: (u32, u32), second: (u32, u32)) -> u32 {
+    (second.0 as f32 * first.1 as f32 / second.1 as f32).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{back_step, forward_step, matched_width, split_spread};
+    use crate::ui::SpreadDirection;
+    use sdl2::rect::Rect;
+
+    #[test]
+    fn test_steps_skip_whole_spreads_and_wide_images_stand_alone() {
+        // Image 2 is a wide double page, 5 is the last image
+        let wide = |i: usize| i == 2;
+        assert_eq!(forward_step(0, 5, &wide), 2);
+        assert_eq!(forward_step(1, 5, &wide), 1);
+        assert_eq!(forward_step(2, 5, &wide), 1);
+        assert_eq!(forward_step(3, 5, &wide), 2);
+        assert_eq!(forward_step(5, 5, &wide), 1);
+
+        assert_eq!(back_step(0, 5, &wide), 1);
+        assert_eq!(back_step(2, 5, &wide), 2);
+        assert_eq!(back_step(3, 5, &wide), 1);
+        assert_eq!(back_step(5, 5, &wide), 2);
+    }
+
+    #[test]
+    fn test_split_spread_places_current_image_by_direction() {
+        let dst = Rect::new(10, 20, 300, 200);
+        let (first, second) = split_spread(dst, 100, 200, SpreadDirection::LeftToRight);
+        assert_eq!(first, Rect::new(10, 20, 100, 200));
+        assert_eq!(second, Rect::new(110, 20, 200, 200));
+
+        let (first, second) = split_spread(dst, 100, 200, SpreadDirection::RightToLeft);
+        assert_eq!(first, Rect::new(210, 20, 100, 200));
+        assert_eq!(second, Rect::new(10, 20, 200, 200));
+    }
+
+    #[test]
+    fn test_matched_width_scales_second_image_to_first_height() {
+        assert_eq!(matched_width((800, 1200), (400, 600)), 800);
+        assert_eq!(matched_width((800, 1200), (1000, 1500)), 800);
+    }
+}
@@ -0,0 +1,382 @@
+//! File that contains functionality that moves, copies and links images into the destination
+//! folder, resolving names already taken there with the conflict policy
+use super::transfer_job::{Task, TransferJob};
+use super::Program;
+use crate::conflict::{ConflictPolicy, Outcome, Report, Resolution};
+use crate::journal::Journal;
+use crate::ui::{self, ConflictAction, Mode};
+use fs_extra::file::{copy, move_file, CopyOptions};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How images are put in the destination folder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Transfer {
+    Copy,
+    Move,
+    Hardlink,
+    Symlink,
+}
+
+impl Transfer {
+    /// What was done to the images, for messages
+    pub(super) fn past_tense(self) -> &'static str {
+        match self {
+            Transfer::Copy => "copied",
+            Transfer::Move => "moved",
+            Transfer::Hardlink => "hardlinked",
+            Transfer::Symlink => "symlinked",
+        }
+    }
+
+    /// What is being done to the images, for the progress shown while they are written
+    pub(super) fn present_participle(self) -> &'static str {
+        match self {
+            Transfer::Copy => "copying",
+            Transfer::Move => "moving",
+            Transfer::Hardlink => "hardlinking",
+            Transfer::Symlink => "symlinking",
+        }
+    }
+
+    /// Name of the change in the journal
+    fn journal_op(self) -> &'static str {
+        match self {
+            Transfer::Copy => "copy",
+            Transfer::Move => "move",
+            Transfer::Hardlink => "hardlink",
+            Transfer::Symlink => "symlink",
+        }
+    }
+
+    /// Whether the images are linked rather than copied or moved
+    fn is_link(self) -> bool {
+        matches!(self, Transfer::Hardlink | Transfer::Symlink)
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Moves, copies or links the images into the destination folder, keeping their file names, and their
+    /// directories relative to the base directory when keeping the directory structure
+    /// Names already taken are resolved before any image is written, then the images are written
+    /// by a worker thread, or pretended to be in a dry run
+    pub(super) fn transfer_images(
+        &mut self,
+        images: &[PathBuf],
+        transfer: Transfer,
+    ) -> Result<String, String> {
+        self.reject_while_transferring()?;
+        self.reject_in_trash(transfer.past_tense())?;
+        if self.dry_run.is_none() {
+            fs::create_dir_all(&self.paths.dest_folder).map_err(|e| e.to_string())?;
+        }
+        let dest_folder = self.paths.dest_folder.clone();
+        let base_dir = if self.keep_structure {
+            Some(self.paths.base_dir.clone())
+        } else {
+            None
+        };
+        let mut policy = self.conflict_policy;
+        // Remote commands can't be asked about, no one may be at the window to answer
+        if self.remote && policy == ConflictPolicy::Prompt {
+            policy = ConflictPolicy::Skip;
+        }
+        let mut report = Report::default();
+        let mut tasks = Vec::new();
+        // Destinations of the images before this one, which are taken once they are written
+        let mut planned = HashSet::new();
+        for (i, path) in images.iter().enumerate() {
+            let dest = match destination(&dest_folder, base_dir.as_deref(), path) {
+                Some(dest) => dest,
+                None => {
+                    let e = "failed to read filename".to_string();
+                    self.journal.failed(transfer.journal_op(), path, None, &e);
+                    report.record(path, Outcome::Failed(e));
+                    continue;
+                }
+            };
+            let resolution = match policy.resolve_among(path, &dest, &planned) {
+                Some(resolution) => resolution,
+                None => match self.ask_conflict(&dest)? {
+                    Some((chosen, all)) => {
+                        if all {
+                            policy = chosen;
+                        }
+                        chosen
+                            .resolve_among(path, &dest, &planned)
+                            .unwrap_or(Resolution::Skip)
+                    }
+                    None => {
+                        // The rest of the images are left where they are
+                        for path in &images[i..] {
+                            journal_outcome(&self.journal, transfer, path, None, &Outcome::Skipped);
+                            report.record(path, Outcome::Skipped);
+                        }
+                        break;
+                    }
+                },
+            };
+            let task = match resolution {
+                Resolution::Skip => {
+                    journal_outcome(
+                        &self.journal,
+                        transfer,
+                        path,
+                        Some(&dest),
+                        &Outcome::Skipped,
+                    );
+                    report.record(path, Outcome::Skipped);
+                    continue;
+                }
+                Resolution::Write(new) if new == dest => Task::new(path, new, false, Outcome::Done),
+                Resolution::Write(new) => {
+                    Task::new(path, new.clone(), false, Outcome::Renamed(new))
+                }
+                Resolution::Replace => Task::new(path, dest, true, Outcome::Replaced),
+            };
+            planned.insert(task.dest.clone());
+            tasks.push(task);
+        }
+
+        let mut job = TransferJob::new(transfer, dest_folder, report);
+        if self.dry_run.is_none() && !tasks.is_empty() {
+            let count = tasks.len();
+            job.start(tasks);
+            self.ui_state.progress = Some(job.progress());
+            self.transfer_job = Some(job);
+            return Ok(format!(
+                "{} {} image(s), press X to cancel",
+                transfer.present_participle(),
+                count
+            ));
+        }
+        // Pretended, or nothing left to write, so finished straight away
+        let mut moved = HashSet::new();
+        for task in tasks {
+            if let Some(dry_run) = self.dry_run.as_mut() {
+                dry_run.record(transfer.past_tense(), &task.src, Some(&task.dest));
+            }
+            moved.extend(job.complete(&self.journal, task, Some(Ok(false))));
+        }
+        // Only images that were moved stop being tracked
+        if !moved.is_empty() {
+            self.paths.retain(|path| !moved.contains(path));
+        }
+        let summary = job.summary()?;
+        if self.dry_run.is_some() {
+            return Ok(format!("{} (dry run)", summary));
+        }
+        Ok(summary)
+    }
+
+    /// Asks what to do with an image whose destination is taken, returning the chosen policy and
+    /// whether it applies to the remaining images too, or None if the rest are cancelled
+    fn ask_conflict(&mut self, dest: &Path) -> Result<Option<(ConflictPolicy, bool)>, String> {
+        self.ui_state.mode = Mode::Conflict(format!(
+            "{} is taken. o: overwrite  s: skip  r: rename  n: keep newer  (shift: all)  Esc: cancel",
+            dest.display()
+        ));
+        self.render_screen(false)?;
+        let choice = self.run_modal(|program, event| match ui::process_conflict_mode(event) {
+            ConflictAction::Choose(policy) => Ok(Some(Some((policy, false)))),
+            ConflictAction::ChooseAll(policy) => Ok(Some(Some((policy, true)))),
+            ConflictAction::Cancel => Ok(Some(None)),
+            ConflictAction::Quit => {
+                program.ui_state.mode = Mode::Exit;
+                Ok(Some(None))
+            }
+            ConflictAction::ReRender => {
+                program.render_screen(false)?;
+                Ok(None)
+            }
+            ConflictAction::Noop => Ok(None),
+        })?;
+        // Quitting is left for the main loop to do
+        if self.ui_state.mode != Mode::Exit {
+            self.ui_state.mode = Mode::Normal;
+        }
+        Ok(choice)
+    }
+}
+
+/// Path in the destination folder an image is put at, the file name of the image or, given the
+/// base directory, its path relative to it
+/// Images outside of the base directory are put straight in the destination folder
+fn destination(dest_folder: &Path, base_dir: Option<&Path>, path: &Path) -> Option<PathBuf> {
+    let relative = base_dir.and_then(|base_dir| path.strip_prefix(base_dir).ok());
+    match relative {
+        Some(relative) if relative.file_name().is_some() => Some(dest_folder.join(relative)),
+        _ => path.file_name().map(|name| dest_folder.join(name)),
+    }
+}
+
+/// Records in the journal how transferring the image at `path` to `dest` turned out
+pub(super) fn journal_outcome(
+    journal: &Journal,
+    transfer: Transfer,
+    path: &Path,
+    dest: Option<&Path>,
+    outcome: &Outcome,
+) {
+    let op = transfer.journal_op();
+    match outcome {
+        Outcome::Done => journal.ok(op, path, dest),
+        Outcome::Renamed(new) => journal.record(op, path, Some(new), "renamed", None),
+        Outcome::Replaced => journal.record(op, path, dest, "replaced", None),
+        Outcome::Skipped => journal.record(op, path, dest, "skipped", None),
+        Outcome::Failed(e) => journal.failed(op, path, dest, e),
+    }
+}
+
+/// Moves, copies or links the image at `src` to `dest`, replacing the file there if `overwrite`
+/// is set
+/// Directories leading to `dest` are created
+/// Returns whether the image was copied because it couldn't be linked
+pub(super) fn transfer_file(
+    src: &Path,
+    dest: &Path,
+    transfer: Transfer,
+    overwrite: bool,
+) -> Result<bool, String> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    // Images can't be removed from archives, so they are extracted and no longer viewed
+    if crate::archive::is_entry(src) {
+        if overwrite {
+            fs::remove_file(dest).map_err(|e| e.to_string())?;
+        }
+        crate::archive::extract_entry(src, dest)?;
+        return Ok(transfer.is_link());
+    }
+    if transfer.is_link() {
+        return link_file(src, dest, transfer, overwrite);
+    }
+    let options = CopyOptions {
+        overwrite,
+        ..CopyOptions::new()
+    };
+    let result = match transfer {
+        Transfer::Move => move_file(src, dest, &options),
+        _ => copy(src, dest, &options),
+    };
+    result.map(|_| false).map_err(|e| e.to_string())
+}
+
+/// Links the image at `src` to `dest`, copying it instead if a hardlink can't cross to the
+/// filesystem of `dest`
+/// A file being replaced is only removed once the link is in place beside it
+fn link_file(src: &Path, dest: &Path, transfer: Transfer, overwrite: bool) -> Result<bool, String> {
+    let link = if overwrite {
+        let name = dest.file_name().unwrap_or_default().to_string_lossy();
+        dest.with_file_name(format!(".{}.riv-link", name))
+    } else {
+        dest.to_path_buf()
+    };
+    let result = match transfer {
+        Transfer::Symlink => symlink(src, &link),
+        _ => fs::hard_link(src, &link),
+    };
+    let copied = match result {
+        Ok(_) => false,
+        Err(e) if transfer == Transfer::Hardlink && crosses_devices(&e) => {
+            fs::copy(src, &link).map_err(|e| e.to_string())?;
+            true
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    if overwrite {
+        if let Err(e) = fs::rename(&link, dest) {
+            let _ = fs::remove_file(&link);
+            return Err(e.to_string());
+        }
+    }
+    Ok(copied)
+}
+
+/// Makes a symlink at `link` to the absolute path of `src`, so it works from any directory
+fn symlink(src: &Path, link: &Path) -> io::Result<()> {
+    let target = fs::canonicalize(src)?;
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_file(target, link);
+}
+
+/// Whether the error is from hardlinking across filesystems
+fn crosses_devices(e: &io::Error) -> bool {
+    // EXDEV on unix, ERROR_NOT_SAME_DEVICE on windows
+    #[cfg(unix)]
+    const CROSS_DEVICE: i32 = 18;
+    #[cfg(windows)]
+    const CROSS_DEVICE: i32 = 17;
+    e.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{destination, transfer_file, Transfer};
+    use crate::test_dir::TestDir;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_directories_are_kept_relative_to_base_dir() {
+        let (keep, base) = (Path::new("/keep"), Path::new("/photos"));
+        let image = Path::new("/photos/2019/trip/a.jpg");
+        assert_eq!(
+            destination(keep, Some(base), image),
+            Some(keep.join("2019/trip/a.jpg"))
+        );
+        assert_eq!(destination(keep, None, image), Some(keep.join("a.jpg")));
+        // Images outside the base directory have no directories to keep
+        let outside = Path::new("/elsewhere/b.jpg");
+        assert_eq!(
+            destination(keep, Some(base), outside),
+            Some(keep.join("b.jpg"))
+        );
+    }
+
+    #[test]
+    fn test_files_are_only_replaced_when_overwriting() {
+        let dir = TestDir::new("transfer");
+        let (src, dest) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&src, "src").unwrap();
+        fs::write(&dest, "dest").unwrap();
+
+        let kept = transfer_file(&src, &dest, Transfer::Copy, false);
+        let kept_contents = fs::read(&dest).unwrap();
+        let replaced = transfer_file(&src, &dest, Transfer::Move, true);
+
+        assert!(kept.is_err());
+        assert_eq!(kept_contents, b"dest");
+        assert!(replaced.is_ok());
+        assert!(!src.exists());
+        assert_eq!(fs::read(&dest).unwrap(), b"src");
+    }
+
+    #[test]
+    fn test_links_replace_files_only_when_overwriting() {
+        let dir = TestDir::new("link");
+        let (src, dest) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&src, "src").unwrap();
+        fs::write(&dest, "dest").unwrap();
+
+        let kept = transfer_file(&src, &dest, Transfer::Hardlink, false);
+        let kept_contents = fs::read(&dest).unwrap();
+        let linked = transfer_file(&src, &dest, Transfer::Symlink, true);
+        let is_symlink = fs::symlink_metadata(&dest)
+            .unwrap()
+            .file_type()
+            .is_symlink();
+        let linked_contents = fs::read(&dest).unwrap();
+
+        assert!(kept.is_err());
+        assert_eq!(kept_contents, b"dest");
+        assert_eq!(linked, Ok(false));
+        assert!(is_symlink);
+        assert_eq!(linked_contents, b"src");
+    }
+}
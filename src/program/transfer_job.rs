@@ -0,0 +1,286 @@
+//! File that contains functionality that writes moved, copied and linked images on a worker
+//! thread, so large transfers don't freeze the window, and takes in what it has finished
+use super::transfer::{journal_outcome, transfer_file, Transfer};
+use super::Program;
+use crate::conflict::{Outcome, Report};
+use crate::journal::Journal;
+use crate::ui::Progress;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// What the worker reports for a task, None if it was cancelled before being started
+type TaskResult = Option<Result<bool, String>>;
+
+/// An image to write to its destination, with its name already resolved
+pub(super) struct Task {
+    /// The image
+    pub(super) src: PathBuf,
+    /// Where the image is written
+    pub(super) dest: PathBuf,
+    /// Whether the file at the destination is replaced
+    overwrite: bool,
+    /// What happened to the image if it is written
+    success: Outcome,
+}
+
+impl Task {
+    /// Task writing the image at `src` to `dest`
+    pub(super) fn new(src: &Path, dest: PathBuf, overwrite: bool, success: Outcome) -> Task {
+        Task {
+            src: src.to_path_buf(),
+            dest,
+            overwrite,
+            success,
+        }
+    }
+}
+
+/// Images being moved, copied or linked, and what has happened to them so far
+pub(super) struct TransferJob {
+    transfer: Transfer,
+    dest_folder: PathBuf,
+    report: Report,
+    /// Number of images given to the worker
+    total: usize,
+    /// Number of images the worker has finished with
+    finished: usize,
+    /// Set to stop the worker before the next image
+    cancel: Arc<AtomicBool>,
+    /// The tasks the worker has finished with, disconnected once it is done
+    results: Option<Receiver<(Task, TaskResult)>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl TransferJob {
+    /// Job putting images in the destination folder, with the outcomes of the images that
+    /// needed no writing already in the report
+    pub(super) fn new(transfer: Transfer, dest_folder: PathBuf, report: Report) -> TransferJob {
+        TransferJob {
+            transfer,
+            dest_folder,
+            report,
+            total: 0,
+            finished: 0,
+            cancel: Arc::new(AtomicBool::new(false)),
+            results: None,
+            worker: None,
+        }
+    }
+
+    /// Starts a worker thread writing the images one at a time, in order
+    pub(super) fn start(&mut self, tasks: Vec<Task>) {
+        self.total = tasks.len();
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::clone(&self.cancel);
+        let transfer = self.transfer;
+        self.worker = Some(thread::spawn(move || {
+            for task in tasks {
+                // The rest of the images are still sent back so they are reported as skipped
+                let result = if cancel.load(Ordering::Relaxed) {
+                    None
+                } else {
+                    Some(transfer_file(
+                        &task.src,
+                        &task.dest,
+                        transfer,
+                        task.overwrite,
+                    ))
+                };
+                if sender.send((task, result)).is_err() {
+                    return;
+                }
+            }
+        }));
+        self.results = Some(receiver);
+    }
+
+    /// How far the worker has got, for the infobar
+    pub(super) fn progress(&self) -> Progress {
+        Progress {
+            verb: self.transfer.present_participle(),
+            done: self.finished,
+            total: self.total,
+        }
+    }
+
+    /// Records what happened to the image of the task, returning its path if it was moved and
+    /// so should no longer be tracked
+    pub(super) fn complete(
+        &mut self,
+        journal: &Journal,
+        task: Task,
+        result: TaskResult,
+    ) -> Option<PathBuf> {
+        self.finished += 1;
+        let outcome = match result {
+            Some(Ok(copied)) => {
+                if copied {
+                    self.report.record_copied(&task.src);
+                }
+                task.success
+            }
+            Some(Err(e)) => Outcome::Failed(e),
+            None => Outcome::Skipped,
+        };
+        let written = matches!(
+            outcome,
+            Outcome::Done | Outcome::Renamed(_) | Outcome::Replaced
+        );
+        journal_outcome(
+            journal,
+            self.transfer,
+            &task.src,
+            Some(&task.dest),
+            &outcome,
+        );
+        self.report.record(&task.src, outcome);
+        if self.transfer == Transfer::Move && written {
+            Some(task.src)
+        } else {
+            None
+        }
+    }
+
+    /// Describes what happened to the images once the job is done
+    pub(super) fn summary(&self) -> Result<String, String> {
+        let summary = self
+            .report
+            .summary(self.transfer.past_tense(), &self.dest_folder);
+        if !self.cancel.load(Ordering::Relaxed) {
+            return summary;
+        }
+        match summary {
+            Ok(s) => Ok(format!("cancelled, {}", s)),
+            Err(e) => Err(format!("cancelled, {}", e)),
+        }
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Takes in the images the worker has finished with, no longer tracking the moved ones
+    /// Waits for the worker to finish if `wait` is set
+    /// Returns the summary of the transfer once every image is done
+    pub(super) fn poll_transfer(&mut self, wait: bool) -> Option<Result<String, String>> {
+        let mut job = self.transfer_job.take()?;
+        let mut moved = HashSet::new();
+        let done = loop {
+            let received = match &job.results {
+                Some(results) if wait => results.recv().map_err(|_| TryRecvError::Disconnected),
+                Some(results) => results.try_recv(),
+                None => Err(TryRecvError::Disconnected),
+            };
+            match received {
+                Ok((task, result)) => moved.extend(job.complete(&self.journal, task, result)),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        // Images only stop being tracked once they are moved
+        if !moved.is_empty() {
+            self.paths.retain(|path| !moved.contains(path));
+            self.screen.dirty = true;
+        }
+        if !done {
+            self.ui_state.progress = Some(job.progress());
+            self.transfer_job = Some(job);
+            return None;
+        }
+        if let Some(worker) = job.worker.take() {
+            let _ = worker.join();
+        }
+        self.ui_state.progress = None;
+        Some(job.summary())
+    }
+
+    /// Returns an error while images are being transferred, as the images given to the worker
+    /// must not be changed or swapped out until it is done with them
+    pub(super) fn reject_while_transferring(&self) -> Result<(), String> {
+        if self.transfer_job.is_some() {
+            return Err("wait for the images being transferred, or press X to cancel".to_string());
+        }
+        Ok(())
+    }
+
+    /// Stops transferring images once the image being written is done
+    pub(super) fn cancel_transfer(&mut self) -> Result<String, String> {
+        match &self.transfer_job {
+            Some(job) => {
+                job.cancel.store(true, Ordering::Relaxed);
+                Ok("cancelling once the image being written is done".to_string())
+            }
+            None => Err("no images are being transferred".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Task, TransferJob};
+    use crate::conflict::{Outcome, Report};
+    use crate::journal::Journal;
+    use crate::program::transfer::Transfer;
+    use crate::test_dir::TestDir;
+    use std::fs;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_worker_moves_images_and_reports_them() {
+        let dir = TestDir::new("job");
+        fs::create_dir_all(dir.join("keep")).unwrap();
+        let tasks = ["a.jpg", "b.jpg"]
+            .iter()
+            .map(|name| {
+                fs::write(dir.join(name), name).unwrap();
+                Task::new(
+                    &dir.join(name),
+                    dir.join("keep").join(name),
+                    false,
+                    Outcome::Done,
+                )
+            })
+            .collect();
+
+        let mut job = TransferJob::new(Transfer::Move, dir.join("keep"), Report::default());
+        job.start(tasks);
+        let mut moved = Vec::new();
+        for (task, result) in job.results.take().unwrap() {
+            moved.extend(job.complete(&Journal::default(), task, result));
+        }
+        let progress = job.progress();
+
+        assert_eq!(moved, vec![dir.join("a.jpg"), dir.join("b.jpg")]);
+        assert_eq!((progress.done, progress.total), (2, 2));
+        assert_eq!(fs::read(dir.join("keep").join("b.jpg")).unwrap(), b"b.jpg");
+        assert!(job.summary().unwrap().starts_with("moved 2 image(s)"));
+    }
+
+    #[test]
+    fn test_cancelled_images_are_skipped() {
+        let dir = TestDir::new("job-cancel");
+        let task = Task::new(
+            &dir.join("a.jpg"),
+            dir.join("keep/a.jpg"),
+            false,
+            Outcome::Done,
+        );
+        let mut job = TransferJob::new(Transfer::Copy, dir.join("keep"), Report::default());
+        job.cancel.store(true, Ordering::Relaxed);
+        job.start(vec![task]);
+        for (task, result) in job.results.take().unwrap() {
+            assert!(result.is_none());
+            job.complete(&Journal::default(), task, result);
+        }
+        assert!(!dir.join("keep").exists());
+        assert_eq!(
+            job.summary(),
+            Ok(format!(
+                "cancelled, copied 0 image(s) to {} (1 skipped)",
+                dir.join("keep").display()
+            ))
+        );
+    }
+}
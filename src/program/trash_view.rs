@@ -0,0 +1,174 @@
+//! File that contains functionality that browses the images in the trash in place of the images
+//! being viewed, and restores them to where they were trashed from
+use super::Program;
+use crate::paths::{Paths, PathsBuilder};
+use crate::trashcan::{self, TrashedImage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The images being viewed before the trash was opened, and what happened while viewing it
+pub(super) struct TrashView {
+    /// Images being viewed before the trash was opened, viewed again once it is closed
+    stash: Paths,
+    /// The trashed images by where they are kept in the trash
+    trashed: HashMap<PathBuf, TrashedImage>,
+    /// Where the images restored while viewing the trash were restored to
+    restored: Vec<PathBuf>,
+}
+
+impl<'a> Program<'a> {
+    /// Opens the trash, viewing the images riv trashed, or every image in it with `all`
+    /// Closes the trash instead if it is open, going back to the images viewed before
+    pub(super) fn toggle_trash(&mut self, arguments: &str) -> Result<String, String> {
+        // The images being moved are tracked by the images being viewed
+        self.reject_while_transferring()?;
+        if self.trash_view.is_some() {
+            return Ok(self.close_trash());
+        }
+        let all = match arguments {
+            "" => false,
+            "all" => true,
+            _ => {
+                return Err(format!(
+                    "Unexpected argument \"{}\" to \":trash\"",
+                    arguments
+                ))
+            }
+        };
+        let trash_dir = trashcan::trash_dir().ok_or("failed to find the trash")?;
+        let mut images = trashcan::trashed_images(&trash_dir)?;
+        if !all {
+            // Images riv trashed in earlier runs are found in the journal
+            let mut trashed = self.journal.trashed();
+            trashed.extend(self.trashed.iter().cloned());
+            images.retain(|image| trashed.contains(&image.original));
+        }
+        if images.is_empty() {
+            return Err(if all {
+                "the trash has no images".to_string()
+            } else {
+                "no images trashed by riv are in the trash, use :trash all to view them all"
+                    .to_string()
+            });
+        }
+
+        let mut files: Vec<PathBuf> = images.iter().map(|image| image.file.clone()).collect();
+        self.sorter.sort(&mut files);
+        let count = files.len();
+        let paths = PathsBuilder::new(files, self.paths.dest_folder.clone(), trash_dir).build();
+        let stash = std::mem::replace(&mut self.paths, paths);
+        self.trash_view = Some(TrashView {
+            stash,
+            trashed: images
+                .into_iter()
+                .map(|image| (image.file.clone(), image))
+                .collect(),
+            restored: Vec::new(),
+        });
+        self.screen.dirty = true;
+        Ok(format!(
+            "viewing {} trashed image(s), :restore restores them and :trash goes back",
+            count
+        ))
+    }
+
+    /// The images being viewed, or those viewed before the trash was opened while viewing it
+    pub(super) fn viewed_paths(&self) -> &Paths {
+        match &self.trash_view {
+            Some(view) => &view.stash,
+            None => &self.paths,
+        }
+    }
+
+    /// Returns an error while viewing the trash, as images in the trash can only be restored
+    /// Anything else would leave the trash with a record of a file it no longer has
+    pub(super) fn reject_in_trash(&self, verb: &str) -> Result<(), String> {
+        if self.trash_view.is_some() {
+            return Err(format!(
+                "images in the trash can't be {}, only restored",
+                verb
+            ));
+        }
+        Ok(())
+    }
+
+    /// Goes back to the images viewed before the trash was opened, along with the images
+    /// restored from it
+    fn close_trash(&mut self) -> String {
+        let view = match self.trash_view.take() {
+            Some(view) => view,
+            None => return String::new(),
+        };
+        self.paths = view.stash;
+        self.screen.dirty = true;
+        if view.restored.is_empty() {
+            return "closed the trash".to_string();
+        }
+
+        let current = self.paths.current_image_path().cloned();
+        let mut images = self.paths.images().to_vec();
+        images.extend(view.restored.iter().cloned());
+        self.sorter.sort(&mut images);
+        self.paths.reload_images(images);
+        let index = current
+            .and_then(|current| self.paths.images().iter().position(|path| path == &current));
+        if let Some(index) = index {
+            self.paths.set_index_safe(index);
+        }
+        format!(
+            "closed the trash, viewing {} restored image(s) again",
+            view.restored.len()
+        )
+    }
+
+    /// Restores the current image and (n-1) next images when a count was typed, otherwise the
+    /// marked images or the current image, to where they were trashed from
+    pub(super) fn restore_images(&mut self, count: usize) -> Result<String, String> {
+        self.reject_dry_run("restoring")?;
+        self.reject_while_transferring()?;
+        if self.trash_view.is_none() {
+            return Err(
+                "images can only be restored from the trash, open it with :trash".to_string(),
+            );
+        }
+        let images = self.selected_images(count)?;
+        let view = self.trash_view.as_mut().unwrap();
+
+        let mut restored = Vec::new();
+        // Store errors for possible future use
+        let mut failures: Vec<String> = Vec::new();
+        for file in &images {
+            let result = match view.trashed.get(file) {
+                Some(image) => image.restore().map(|_| image.original.clone()),
+                None => Err("not in the trash".to_string()),
+            };
+            match result {
+                Ok(original) => {
+                    self.journal.ok("restore", file, Some(&original));
+                    view.trashed.remove(file);
+                    view.restored.push(original);
+                    restored.push(file.clone());
+                }
+                Err(e) => {
+                    self.journal.failed("restore", file, None, &e);
+                    let failure = format!("{}: {}", file.display(), e);
+                    eprintln!("Failed to restore {}", failure);
+                    failures.push(failure);
+                }
+            }
+        }
+        self.paths.retain(|path| !restored.contains(path));
+        self.screen.dirty = true;
+
+        if failures.is_empty() {
+            Ok(format!("restored {} image(s)", restored.len()))
+        } else {
+            Err(format!(
+                "Failed to restore {} of {} images: {}",
+                failures.len(),
+                images.len(),
+                failures[0]
+            ))
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! Screen contains the Screen struct which contains all SDL initialised data required
 //! for building the window and rendering to screen.
+use crate::orientation::Orientation;
 use sdl2::render::{TextureCreator, WindowCanvas};
 use sdl2::ttf::Font;
 use sdl2::video::{FullscreenType, WindowContext};
@@ -22,6 +23,16 @@ pub struct Screen<'a> {
     pub last_index: Option<usize>,
     /// last_texture is the last image texture rendered
     pub last_texture: Option<sdl2::render::Texture<'a>>,
+    /// last_orientation is the orientation the file of last_texture says to show it in
+    pub last_orientation: Orientation,
+    /// second_texture is the image shown next to last_texture in a two page spread
+    pub second_texture: Option<sdl2::render::Texture<'a>>,
+    /// second_orientation is the orientation the file of second_texture says to show it in
+    pub second_orientation: Orientation,
+    /// pinned_texture is the image last_texture is compared against
+    pub pinned_texture: Option<sdl2::render::Texture<'a>>,
+    /// pinned_orientation is the orientation the file of pinned_texture says to show it in
+    pub pinned_orientation: Orientation,
     /// dirty, if true indicates that last texture must be discarded
     pub dirty: bool,
 }
@@ -0,0 +1,125 @@
+//! # Session
+//!
+//! The session module saves what is being viewed, the paths searched, how the images are sorted,
+//! the marks and the current image, so culling can carry on where it was left in a later run.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the session used when none is given
+pub const DEFAULT_SESSION: &str = "default";
+
+/// Everything needed to view the same images in the same place again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Directory the globs are relative to
+    pub search_dir: PathBuf,
+    /// The paths, directories or globs searched for images
+    pub globs: Vec<String>,
+    /// Name of the sort order, as accepted by `--sort-order`
+    pub sort_order: String,
+    /// Whether the sort order is reversed
+    pub reverse: bool,
+    /// Maximum number of images viewed, 0 for no maximum
+    pub max: usize,
+    /// Folder images are moved and copied to
+    pub dest_folder: PathBuf,
+    /// The marked images
+    #[serde(default)]
+    pub marks: Vec<PathBuf>,
+    /// The image being viewed
+    #[serde(default)]
+    pub current: Option<PathBuf>,
+}
+
+impl Session {
+    /// Writes the session under the name, replacing the session saved under it before
+    pub fn save(&self, name: &str) -> Result<(), String> {
+        self.save_to(&session_path(name)?)
+    }
+
+    /// Reads the session saved under the name
+    pub fn load(name: &str) -> Result<Session, String> {
+        Session::load_from(&session_path(name)?)
+    }
+
+    /// Writes the session to the file at the path, creating its directory if needed
+    fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        // Written beside the session first so a failed write doesn't lose the saved session
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, path).map_err(|e| e.to_string())
+    }
+
+    /// Reads the session from the file at the path
+    fn load_from(path: &Path) -> Result<Session, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// Whether a session is saved under the name
+pub fn exists(name: &str) -> bool {
+    session_path(name).is_ok_and(|path| path.is_file())
+}
+
+/// Directory sessions are saved in, `$XDG_STATE_HOME/riv/sessions`
+/// Falls back to `~/.local/state/riv/sessions` if `$XDG_STATE_HOME` is not set
+fn sessions_dir() -> Option<PathBuf> {
+    let state_dir = crate::xdg_dir("XDG_STATE_HOME", ".local/state")?;
+    Some(state_dir.join("riv").join("sessions"))
+}
+
+/// Path of the file the session with the name is saved in
+fn session_path(name: &str) -> Result<PathBuf, String> {
+    check_name(name)?;
+    let dir = sessions_dir().ok_or("failed to find a directory to keep sessions in")?;
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+/// Returns an error unless the name can be used as a file name
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("\"{}\" can't be used as a session name", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_name, Session};
+    use crate::test_dir::TestDir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_sessions_are_saved_and_loaded() {
+        let dir = TestDir::new("session");
+        let path = dir.join("sessions").join("cull.json");
+        let session = Session {
+            search_dir: PathBuf::from("/photos"),
+            globs: vec!["2019".to_string(), "*.jpg".to_string()],
+            sort_order: "Date".to_string(),
+            reverse: true,
+            max: 50,
+            dest_folder: PathBuf::from("/photos/keep"),
+            marks: vec![PathBuf::from("/photos/2019/a.jpg")],
+            current: Some(PathBuf::from("/photos/2019/b.jpg")),
+        };
+        session.save_to(&path).unwrap();
+
+        assert_eq!(Session::load_from(&path), Ok(session));
+    }
+
+    #[test]
+    fn test_session_names_are_file_names() {
+        assert!(check_name("march-shoot").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("../journal").is_err());
+        assert!(check_name(".hidden").is_err());
+    }
+}
@@ -6,6 +6,7 @@
 // inside of arg!_enum macro call
 #![allow(missing_docs)]
 
+use crate::archive::containing_file;
 use core::cmp::Ordering;
 use fs_extra::dir::get_size;
 use std::ffi::OsStr;
@@ -41,6 +42,16 @@ impl Sorter {
         self.reverse = reverse;
     }
 
+    /// The order files are sorted in
+    pub fn order(&self) -> &SortOrder {
+        &self.sort_order
+    }
+
+    /// Whether the sorting of images is reversed
+    pub fn reversed(&self) -> bool {
+        self.reverse
+    }
+
     /// Sorts the images based on sort_order, reverses if necessary
     pub fn sort<'a>(&self, paths: &'a mut [PathBuf]) -> &'a mut [PathBuf] {
         paths.sort_by(|a, b| self.sort_order.file_compare(&a, &b));
@@ -73,7 +84,10 @@ impl SortOrder {
     fn file_compare(&self, a: &PathBuf, b: &PathBuf) -> Ordering {
         match self {
             // if get_size fails just assume that it's size 0
-            SortOrder::Size => get_size(b).unwrap_or(0).cmp(&get_size(a).unwrap_or(0)),
+            // images inside archives are sorted by their archive
+            SortOrder::Size => get_size(containing_file(b))
+                .unwrap_or(0)
+                .cmp(&get_size(containing_file(a)).unwrap_or(0)),
             SortOrder::Date => file_get_date(b).cmp(&file_get_date(a)),
             SortOrder::Alphabetical => {
                 let a_no_dot = trim_hidden(a.file_stem());
@@ -103,8 +117,9 @@ fn trim_hidden(file_name: Option<&OsStr>) -> String {
 }
 
 /// Get the time a file was last modified, if this function fails it returns Now
+/// Images inside archives use the time of the archive
 fn file_get_date(path: &PathBuf) -> SystemTime {
-    let metadata = match path.metadata() {
+    let metadata = match containing_file(path).metadata() {
         Ok(data) => data,
         Err(e) => {
             eprintln!("{}", e);
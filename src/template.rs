@@ -0,0 +1,190 @@
+//! Filename templates name the files images are written to, tokens in braces are replaced with
+//! details of the image being named
+use crate::exif;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime};
+use std::path::Path;
+
+/// Format of `{date}` when no format is given
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+/// Format of the dates in EXIF data
+const EXIF_DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// Details of an image that the tokens of a template are replaced with
+pub struct Fields<'a> {
+    /// Path of the image being named
+    pub path: &'a Path,
+    /// Position of the image among the images being named, starting at 1
+    pub n: usize,
+    /// Extension of the file being named, without the leading `.`
+    pub ext: &'a str,
+}
+
+/// Replaces the tokens in `template` with details from `fields`
+///
+/// * `{name}` the file name of the image without its extension
+/// * `{ext}` the extension of the file being named
+/// * `{n}` the position of the image, `{n:04}` pads it with zeros to 4 digits
+/// * `{date}` the date the photo was taken, or the file was last modified when it has no EXIF
+///   date, `{date:%Y%m%d}` formats it with strftime specifiers
+/// * `{exif.make}` and `{exif.model}` the camera the photo was taken with, `unknown` if the
+///   image doesn't record it
+/// * `{{` and `}}` literal braces
+///
+/// Returns an error for unknown or unclosed tokens and for names that aren't a single file name
+pub fn expand(template: &str, fields: &Fields) -> Result<String, String> {
+    let mut name = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                name.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                name.push('}');
+            }
+            '{' => {
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(format!("unclosed token \"{{{}\"", token)),
+                    }
+                }
+                name.push_str(&expand_token(&token, fields)?);
+            }
+            '}' => return Err(format!("unmatched \"}}\" in \"{}\"", template)),
+            c => name.push(c),
+        }
+    }
+    if name.is_empty() || name == "." || name == ".." || name.chars().any(std::path::is_separator) {
+        return Err(format!("\"{}\" is not a file name", name));
+    }
+    Ok(name)
+}
+
+/// Value of a single token, the text between the braces
+fn expand_token(token: &str, fields: &Fields) -> Result<String, String> {
+    let mut split = token.splitn(2, ':');
+    let key = split.next().unwrap_or("");
+    let spec = split.next();
+    match (key, spec) {
+        ("name", None) => Ok(fields
+            .path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()),
+        ("ext", None) => Ok(fields.ext.to_string()),
+        ("n", None) => Ok(fields.n.to_string()),
+        ("n", Some(spec)) => pad_number(fields.n, spec),
+        ("date", spec) => format_date(
+            &image_date(fields.path),
+            spec.unwrap_or(DEFAULT_DATE_FORMAT),
+        ),
+        ("exif.make", None) => Ok(exif_text(exif::read_tags(fields.path).make)),
+        ("exif.model", None) => Ok(exif_text(exif::read_tags(fields.path).model)),
+        _ => Err(format!("unknown token \"{{{}}}\"", token)),
+    }
+}
+
+/// When the photo at the path was taken, falling back on when the file was last modified
+/// Images without either are dated now
+fn image_date(path: &Path) -> NaiveDateTime {
+    let taken = exif::read_tags(path)
+        .date_taken
+        .and_then(|date| NaiveDateTime::parse_from_str(&date, EXIF_DATE_FORMAT).ok());
+    if let Some(taken) = taken {
+        return taken;
+    }
+    let modified = path.metadata().and_then(|metadata| metadata.modified());
+    match modified {
+        Ok(modified) => DateTime::<Local>::from(modified).naive_local(),
+        Err(_) => Local::now().naive_local(),
+    }
+}
+
+/// Formats the date with strftime specifiers, such as `%Y%m%d`
+fn format_date(date: &NaiveDateTime, format: &str) -> Result<String, String> {
+    let items: Vec<Item> = StrftimeItems::new(format).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return Err(format!("\"{}\" is not a date format", format));
+    }
+    Ok(date.format_with_items(items.into_iter()).to_string())
+}
+
+/// Text from the EXIF data made safe to use in a file name
+fn exif_text(text: Option<String>) -> String {
+    match text {
+        Some(text) => text.replace(std::path::is_separator, "_"),
+        None => "unknown".to_string(),
+    }
+}
+
+/// Formats a number to the width in `spec`, a leading `0` pads it with zeros instead of spaces
+fn pad_number(n: usize, spec: &str) -> Result<String, String> {
+    let width = spec
+        .parse::<usize>()
+        .map_err(|_| format!("\"{}\" is not a width", spec))?;
+    if spec.starts_with('0') {
+        Ok(format!("{:0width$}", n, width = width))
+    } else {
+        Ok(format!("{:width$}", n, width = width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exif_text, expand, format_date, Fields};
+    use chrono::NaiveDate;
+    use std::path::Path;
+
+    fn fields(n: usize) -> Fields<'static> {
+        Fields {
+            path: Path::new("/photos/beach.png"),
+            n,
+            ext: "jpg",
+        }
+    }
+
+    #[test]
+    fn test_tokens_are_replaced() {
+        let name = expand("{name}_web.{ext}", &fields(1)).unwrap();
+        assert_eq!(name, "beach_web.jpg");
+    }
+
+    #[test]
+    fn test_number_is_padded_to_width() {
+        assert_eq!(expand("{n:04}.{ext}", &fields(7)).unwrap(), "0007.jpg");
+        assert_eq!(expand("{n}.{ext}", &fields(12)).unwrap(), "12.jpg");
+        assert_eq!(expand("{{{n:3}}}", &fields(5)).unwrap(), "{  5}");
+    }
+
+    #[test]
+    fn test_dates_are_formatted() {
+        let date = NaiveDate::from_ymd_opt(2021, 3, 9)
+            .and_then(|date| date.and_hms_opt(14, 5, 0))
+            .unwrap();
+        assert_eq!(format_date(&date, "%Y%m%d").unwrap(), "20210309");
+        assert_eq!(format_date(&date, "%d.%m_%H%M").unwrap(), "09.03_1405");
+        assert!(format_date(&date, "%Q").is_err());
+    }
+
+    #[test]
+    fn test_missing_exif_details_are_unknown() {
+        let name = expand("{exif.model}_{n:02}", &fields(3)).unwrap();
+        assert_eq!(name, "unknown_03");
+        assert_eq!(exif_text(Some("EOS 5D/II".to_string())), "EOS 5D_II");
+    }
+
+    #[test]
+    fn test_invalid_templates_are_rejected() {
+        assert!(expand("{size}.{ext}", &fields(1)).is_err());
+        assert!(expand("{name.{ext}", &fields(1)).is_err());
+        assert!(expand("{name}}", &fields(1)).is_err());
+        assert!(expand("out/{name}.{ext}", &fields(1)).is_err());
+    }
+}
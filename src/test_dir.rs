@@ -0,0 +1,44 @@
+//! # Test dir
+//!
+//! The test dir module gives tests a directory of their own to write files in, which is removed
+//! once the test is done with it, even if the test fails.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory in the temporary directory, removed with everything in it when dropped
+#[derive(Debug)]
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Creates the directory for the test, `name` keeps it apart from the directories of other
+    /// tests running at the same time
+    pub(crate) fn new(name: &str) -> TestDir {
+        let dir = std::env::temp_dir().join(format!("riv-{}-{}", name, std::process::id()));
+        // Left over from a run that was killed before it could clean up
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = PathBuf;
+
+    fn deref(&self) -> &PathBuf {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
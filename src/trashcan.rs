@@ -0,0 +1,158 @@
+//! # Trashcan
+//!
+//! The trashcan module finds the images in the XDG trash and restores them to where they were
+//! trashed from, using the `.trashinfo` file the trash keeps for every trashed file.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An image in the trash
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedImage {
+    /// Where the image is kept in the trash
+    pub file: PathBuf,
+    /// Where the image was trashed from
+    pub original: PathBuf,
+    /// The `.trashinfo` file recording where the image was trashed from
+    info: PathBuf,
+}
+
+impl TrashedImage {
+    /// Moves the image back to where it was trashed from and forgets it was trashed
+    /// Fails rather than overwrite a file that has since taken its place
+    pub fn restore(&self) -> Result<(), String> {
+        if self.original.exists() {
+            return Err(format!("{} already exists", self.original.display()));
+        }
+        if let Some(parent) = self.original.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        // The trash is often on another filesystem than the original directory
+        if fs::rename(&self.file, &self.original).is_err() {
+            let options = fs_extra::file::CopyOptions::new();
+            fs_extra::file::move_file(&self.file, &self.original, &options)
+                .map_err(|e| e.to_string())?;
+        }
+        fs::remove_file(&self.info).map_err(|e| e.to_string())
+    }
+}
+
+/// The trash of the user, `$XDG_DATA_HOME/Trash` falling back on `~/.local/share/Trash`
+pub fn trash_dir() -> Option<PathBuf> {
+    let data_dir = crate::xdg_dir("XDG_DATA_HOME", ".local/share")?;
+    Some(data_dir.join("Trash"))
+}
+
+/// The images in the trash at `trash_dir`, files without a readable `.trashinfo` are skipped
+pub fn trashed_images(trash_dir: &Path) -> Result<Vec<TrashedImage>, String> {
+    let info_dir = trash_dir.join("info");
+    let entries = fs::read_dir(&info_dir)
+        .map_err(|e| format!("failed to read {}: {}", info_dir.display(), e))?;
+    let mut images = Vec::new();
+    for entry in entries.flatten() {
+        let info = entry.path();
+        if info.extension() != Some(OsStr::new("trashinfo")) {
+            continue;
+        }
+        let file = match info.file_stem() {
+            Some(name) => trash_dir.join("files").join(name),
+            None => continue,
+        };
+        if !file.is_file() || !crate::cli::is_image_path(&file) {
+            continue;
+        }
+        let original = fs::read_to_string(&info)
+            .ok()
+            .and_then(|text| parse_trash_info(&text));
+        if let Some(original) = original {
+            images.push(TrashedImage {
+                file,
+                original,
+                info,
+            });
+        }
+    }
+    Ok(images)
+}
+
+/// The original path recorded in the text of a `.trashinfo` file
+fn parse_trash_info(text: &str) -> Option<PathBuf> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("[Trash Info]") {
+        return None;
+    }
+    let path = lines.find_map(|line| line.strip_prefix("Path="))?;
+    let path = PathBuf::from(percent_decode(path)?);
+    // Relative paths are relative to the directory holding the trash, which is the root
+    // directory for the trash in the home directory
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(Path::new("/").join(path))
+    }
+}
+
+/// Decodes the `%XX` escapes the trash uses in paths
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_trash_info, trashed_images};
+    use crate::test_dir::TestDir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_original_path_is_decoded() {
+        let info = "[Trash Info]\nPath=/home/me/My%20Photos/caf%C3%A9.jpg\nDeletionDate=2020-05-17T10:30:00\n";
+        assert_eq!(
+            parse_trash_info(info),
+            Some(PathBuf::from("/home/me/My Photos/café.jpg"))
+        );
+        assert_eq!(parse_trash_info("Path=/a.jpg"), None);
+        assert_eq!(parse_trash_info("[Trash Info]\nPath=/a%2.jpg"), None);
+    }
+
+    #[test]
+    fn test_trashed_images_are_restored() {
+        let dir = TestDir::new("trashcan");
+        let trash = dir.join("Trash");
+        fs::create_dir_all(trash.join("files")).unwrap();
+        fs::create_dir_all(trash.join("info")).unwrap();
+        let original = dir.join("photos").join("a.jpg");
+        fs::write(trash.join("files/a.jpg"), "a").unwrap();
+        let info = format!("[Trash Info]\nPath={}\n", original.display());
+        fs::write(trash.join("info/a.jpg.trashinfo"), info).unwrap();
+        // Other files and files without a .trashinfo are left out
+        fs::write(trash.join("files/notes.txt"), "").unwrap();
+        fs::write(trash.join("files/b.jpg"), "b").unwrap();
+
+        let images = trashed_images(&trash).unwrap();
+        let restored = images
+            .iter()
+            .map(|image| image.restore())
+            .collect::<Vec<_>>();
+
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].original, original);
+        assert_eq!(restored, vec![Ok(())]);
+        assert_eq!(fs::read(&original).unwrap(), b"a");
+        assert!(!trash.join("info/a.jpg.trashinfo").exists());
+    }
+}
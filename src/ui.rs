@@ -2,9 +2,13 @@
 //!
 //! The UI module contains logic for matching keyboard and system events
 
+use crate::conflict::ConflictPolicy;
 use sdl2::event::Event;
 use sdl2::keyboard::Mod;
-use sdl2::mouse::MouseButton;
+use sdl2::mouse::{MouseButton, MouseWheelDirection};
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Instant;
 
 /// Action represents the possible actions that could result from an event
@@ -30,6 +34,8 @@ pub enum Action<'a> {
     /// The app should switch its current image viewing preference of fitting the
     /// image to screen or displaying the actual size as actual size
     ToggleFit,
+    /// Switches to the next way of fitting the image to the window
+    CycleFitMode,
     /// Centres the image
     CenterImage,
     /// Flip the image horizontally
@@ -50,6 +56,10 @@ pub enum Action<'a> {
     SkipBack,
     /// Zoom zooms in or out depending on the ZoomAction variant
     Zoom(ZoomAction),
+    /// Zooms in or out keeping the point of the image at the window position (x, y) in place
+    ZoomAt(ZoomAction, i32, i32),
+    /// Pans the image by the distance in pixels the mouse was dragged
+    Drag(i32, i32),
     /// Which direction to rotate image
     Rotate(RotationDirection),
     /// Pan pans the picture in the direction of the PanAction variant
@@ -58,10 +68,36 @@ pub enum Action<'a> {
     Copy,
     /// Move indicates the app should move the image in response to this event
     Move,
+    /// Hardlinks the image into the destination folder
+    Hardlink,
+    /// Symlinks the image into the destination folder
+    Symlink,
     /// Delete indicates the app should delete the image in response to this event
     Delete,
     /// Trash indicates the app should move the image to a trash folder
     Trash,
+    /// Toggles whether the current image is marked
+    ToggleMark,
+    /// Toggles showing two images side by side as pages of a book
+    ToggleSpread,
+    /// Swaps the reading direction of the two page spread
+    SwapSpreadDirection,
+    /// Pins the current image to compare other images against, or stops comparing
+    ToggleCompare,
+    /// Pins the compared image in place of the pinned image
+    SwapCompare,
+    /// Toggles alternating the pinned and compared images in the same place
+    ToggleFlicker,
+    /// Switches to crop mode to select part of the image to save
+    Crop,
+    /// Stops moving, copying or linking images once the image being written is done
+    CancelTransfer,
+    /// Bookmarks the current image under the letter
+    SetBookmark(char),
+    /// Jumps to the image bookmarked under the letter
+    JumpToBookmark(char),
+    /// Runs the external shell command, placeholders are substituted before running
+    Exec(String),
     /// Noop indicates the app should not respond to this event
     Noop,
 }
@@ -165,7 +201,7 @@ pub enum ZoomAction {
 }
 
 /// PanAction contains the variants of a possible pan action. Left | Right | Up | Down
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PanAction {
     /// Left pans left
     Left,
@@ -193,6 +229,16 @@ pub enum Mode {
     Error(String),
     /// Mode that is used to display success messages
     Success(String),
+    /// Mode for selecting part of the current image to save as a new image
+    Crop,
+    /// Mode that previews the renames `:rename` will make until they are confirmed or cancelled
+    Rename,
+    /// Mode that asks what to do with an image being moved or copied to a name that is taken
+    /// string is the question to display on the infobar
+    Conflict(String),
+    /// Mode that asks to confirm a destructive action before performing it
+    /// string is the question to display on the infobar
+    Confirm(String),
     /// Terminate condition, if this mode is set the program will stop execution
     Exit,
 }
@@ -240,11 +286,11 @@ pub struct State<'a> {
     pub pan_x: f32,
     /// pan_y is the degree of pan in the y axis
     pub pan_y: f32,
-    /// Image is flipped horizontally from original state
+    /// Image is flipped horizontally from the orientation its file says to show it in
     pub flip_horizontal: bool,
-    /// Image is flipped vertically from original state
+    /// Image is flipped vertically from the orientation its file says to show it in
     pub flip_vertical: bool,
-    /// Angle to rotate original image at
+    /// Angle to rotate the image at, on top of the orientation its file says to show it in
     /// Only supports 90 degree increments specified in `RotAngle` enum
     pub rot_angle: RotAngle,
     /// The time, from which to do a re-render will be base on.
@@ -252,9 +298,302 @@ pub struct State<'a> {
     pub rerender_time: Option<Instant>,
     /// Store
     pub register: Register<'a>,
+    /// Shell commands bound to keys in normal mode, keyed by the text the key inputs
+    pub bindings: HashMap<String, String>,
+    /// Whether two images are shown side by side
+    pub spread: bool,
+    /// Reading direction of the two page spread
+    pub spread_direction: SpreadDirection,
+    /// Comparing the current image against a pinned image, None when not comparing
+    pub compare: Option<Compare>,
+    /// Last known position of the mouse cursor in the window
+    pub cursor: (i32, i32),
+    /// How the current image is fit to the window, None once it has been zoomed by hand
+    pub fit_mode: Option<FitMode>,
+    /// How new images are fit to the window
+    pub default_fit: FitMode,
+    /// Whether the fit mode and zoom are kept when moving to another image
+    pub keep_view: bool,
+    /// Selection made in crop mode, None when not cropping
+    pub crop: Option<Crop>,
+    /// Count typed before switching to command mode, commands that work on several images use
+    /// it as the number of images, 1 when no count was typed
+    pub command_count: usize,
+    /// Renames previewed in rename mode, each image path and the path it is renamed to
+    pub rename: Option<Vec<(PathBuf, PathBuf)>>,
+    /// Action waiting to be confirmed in confirm mode
+    pub pending: Option<ProcessAction<'a>>,
+    /// Whether the next action was confirmed, so it isn't asked about again
+    pub confirmed: bool,
+    /// How far moving, copying or linking images has got, None when no images are being written
+    pub progress: Option<Progress>,
+    /// Bookmark key waiting for the letter typed after it, None when no bookmark key was pressed
+    pub bookmark_key: Option<BookmarkKey>,
+}
+
+/// Key that starts bookmarking an image or jumping to one, the letter comes next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookmarkKey {
+    /// `M` bookmarks the current image
+    Set,
+    /// `'` jumps to a bookmarked image
+    Jump,
+}
+
+/// How many of the images being moved, copied or linked have been written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// What is being done to the images, such as "copying"
+    pub verb: &'static str,
+    /// Number of images finished with
+    pub done: usize,
+    /// Number of images being written
+    pub total: usize,
+}
+
+/// Part of the current image selected in crop mode
+#[derive(Debug, Clone)]
+pub struct Crop {
+    /// Selected area in pixels of the image as it is stored
+    pub selection: Rect,
+    /// Window position the mouse started dragging a new selection from
+    pub anchor: Option<(i32, i32)>,
+    /// Width to height ratio the selection is locked to, as the image is displayed
+    pub aspect: Option<(u32, u32)>,
+    /// Whether the next overwrite will replace the image, asked before overwriting it
+    pub confirm_overwrite: bool,
+    /// Whether the image is shown on its side, so the selection's width and height are swapped
+    pub sideways: bool,
+}
+
+/// Actions available in crop mode
+#[derive(Debug, Clone, PartialEq)]
+pub enum CropAction {
+    /// Starts a new selection at the window position
+    Start(i32, i32),
+    /// Stretches the selection being made to the window position
+    Drag(i32, i32),
+    /// Finishes the selection being made
+    Release,
+    /// Moves the selection in the direction
+    Move(PanAction),
+    /// Moves the bottom right corner of the selection in the direction
+    Resize(PanAction),
+    /// Switches to the next aspect ratio to lock the selection to
+    CycleAspect,
+    /// Saves the selection as a new image in the destination folder
+    Save,
+    /// Replaces the image with the selection, asking first
+    Overwrite,
+    /// Leaves crop mode
+    Cancel,
+    /// Re-renders the screen, such as after a window resize
+    ReRender,
+    /// Quits the app
+    Quit,
+    /// Nothing to do
+    Noop,
+}
+
+/// Answers to a question that needs confirming, such as previewed renames
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfirmAction {
+    /// Goes ahead
+    Confirm,
+    /// Backs out
+    Cancel,
+    /// Re-renders the screen, such as after a window resize
+    ReRender,
+    /// Quits the app
+    Quit,
+    /// Nothing to do
+    Noop,
+}
+
+/// Answer to what to do with an image being moved or copied to a name that is taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictAction {
+    /// Resolves this conflict with the policy
+    Choose(ConflictPolicy),
+    /// Resolves this and every later conflict with the policy
+    ChooseAll(ConflictPolicy),
+    /// Leaves this and the remaining images where they are
+    Cancel,
+    /// Re-renders the screen, such as after a window resize
+    ReRender,
+    /// Quits the app
+    Quit,
+    /// Nothing to do
+    Noop,
+}
+
+/// How an image is scaled to the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FitMode {
+    /// Shrinks images larger than the window to fit, smaller images are shown at actual size
+    Fit,
+    /// Scales the image so its width fills the window
+    Width,
+    /// Scales the image so its height fills the window
+    Height,
+    /// Scales the image to cover the whole window, cropping whatever doesn't fit
+    Fill,
+    /// Shows the image at its actual size
+    Actual,
+    /// Scales images larger or smaller than the window to fit
+    Upscale,
+}
+
+impl FitMode {
+    /// Names of the fit modes, as accepted by `FromStr`
+    pub const NAMES: [&'static str; 6] = ["fit", "width", "height", "fill", "actual", "upscale"];
+
+    /// The fit mode after this one when cycling through them
+    pub fn next(self) -> FitMode {
+        match self {
+            FitMode::Fit => FitMode::Width,
+            FitMode::Width => FitMode::Height,
+            FitMode::Height => FitMode::Fill,
+            FitMode::Fill => FitMode::Actual,
+            FitMode::Actual => FitMode::Upscale,
+            FitMode::Upscale => FitMode::Fit,
+        }
+    }
+
+    /// Scale to display an image of size `src` at within an area of size `dst`
+    pub fn scale(self, src: (u32, u32), dst: (u32, u32)) -> f32 {
+        let scale_x = dst.0 as f32 / src.0 as f32;
+        let scale_y = dst.1 as f32 / src.1 as f32;
+        match self {
+            FitMode::Fit if src.0 < dst.0 && src.1 < dst.1 => 1.0,
+            FitMode::Fit | FitMode::Upscale => scale_x.min(scale_y),
+            FitMode::Width => scale_x,
+            FitMode::Height => scale_y,
+            FitMode::Fill => scale_x.max(scale_y),
+            FitMode::Actual => 1.0,
+        }
+    }
+}
+
+impl std::str::FromStr for FitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FitMode, String> {
+        match s.to_lowercase().as_str() {
+            "fit" => Ok(FitMode::Fit),
+            "width" => Ok(FitMode::Width),
+            "height" => Ok(FitMode::Height),
+            "fill" | "crop" => Ok(FitMode::Fill),
+            "actual" => Ok(FitMode::Actual),
+            "upscale" => Ok(FitMode::Upscale),
+            _ => Err(format!(
+                "No such fit mode \"{}\", expected one of {}",
+                s,
+                FitMode::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for FitMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            FitMode::Fit => "fit",
+            FitMode::Width => "width",
+            FitMode::Height => "height",
+            FitMode::Fill => "fill",
+            FitMode::Actual => "actual",
+            FitMode::Upscale => "upscale",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Numbers of images at which destructive actions ask to be confirmed first, 0 never asks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfirmThresholds {
+    /// Deleting images with `D`
+    pub delete: usize,
+    /// Trashing images with `d`
+    pub trash: usize,
+    /// Moving images with `m`
+    pub move_images: usize,
+}
+
+impl Default for ConfirmThresholds {
+    /// Deleting always asks, trashing and moving never ask
+    fn default() -> Self {
+        Self {
+            delete: 1,
+            trash: 0,
+            move_images: 0,
+        }
+    }
+}
+
+impl ConfirmThresholds {
+    /// Names of the actions that can ask to be confirmed
+    pub const NAMES: [&'static str; 3] = ["delete", "trash", "move"];
+
+    /// Sets the threshold of the action with the name
+    pub fn set(&mut self, name: &str, count: &str) -> Result<(), String> {
+        let count = count
+            .parse::<usize>()
+            .map_err(|_| format!("\"{}\" is not a number of images", count))?;
+        match name.to_lowercase().as_str() {
+            "delete" => self.delete = count,
+            "trash" => self.trash = count,
+            "move" => self.move_images = count,
+            _ => {
+                return Err(format!(
+                    "No action \"{}\" to confirm, expected one of {}",
+                    name,
+                    ConfirmThresholds::NAMES.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// The question to ask before performing the action on `count` images, None if it doesn't
+    /// need to be confirmed
+    pub fn question(&self, action: &Action, count: usize) -> Option<String> {
+        let (verb, threshold) = match action {
+            Action::Delete => ("Delete", self.delete),
+            Action::Trash => ("Trash", self.trash),
+            Action::Move => ("Move", self.move_images),
+            _ => return None,
+        };
+        if threshold == 0 || count < threshold {
+            return None;
+        }
+        let files = if count == 1 { "file" } else { "files" };
+        Some(format!("{} {} {}? y/N", verb, count, files))
+    }
+}
+
+/// State of comparing images against a pinned image
+#[derive(Debug, Clone)]
+pub struct Compare {
+    /// The image shown on the left, the current image is shown on the right
+    pub pinned: PathBuf,
+    /// When the flicker last switched images, None when the images are shown side by side
+    pub flicker: Option<Instant>,
+    /// Whether the flicker is showing the pinned image
+    pub showing_pinned: bool,
+}
+
+/// Reading direction of the two page spread, which side the current image is shown on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpreadDirection {
+    /// Current image on the left and the next image on the right, like western comics
+    LeftToRight,
+    /// Current image on the right and the next image on the left, like manga
+    RightToLeft,
 }
 
 /// Rotation angle for image
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RotAngle {
     /// 0 degree rotation
     Up,
@@ -285,6 +624,10 @@ impl RotAngle {
             RotAngle::Right => RotAngle::Up,
         }
     }
+    /// Whether the image is on its side, so its width and height are swapped on screen
+    pub fn is_sideways(&self) -> bool {
+        matches!(self, RotAngle::Right | RotAngle::Left)
+    }
 }
 
 impl<'a> Default for State<'a> {
@@ -305,6 +648,21 @@ impl<'a> Default for State<'a> {
             register: Register {
                 ..Default::default()
             },
+            bindings: HashMap::new(),
+            spread: false,
+            spread_direction: SpreadDirection::LeftToRight,
+            compare: None,
+            cursor: (0, 0),
+            fit_mode: Some(FitMode::Fit),
+            default_fit: FitMode::Fit,
+            keep_view: false,
+            crop: None,
+            command_count: 1,
+            rename: None,
+            pending: None,
+            confirmed: false,
+            progress: None,
+            bookmark_key: None,
         }
     }
 }
@@ -316,6 +674,7 @@ impl<'a> State<'a> {
         let zoom_times = cap_zoom_times(times);
 
         self.scale *= zoom_factor.powi(zoom_times);
+        self.fit_mode = None;
     }
 
     /// Decreases zoom scale. Does not render image
@@ -324,6 +683,7 @@ impl<'a> State<'a> {
         let zoom_times = cap_zoom_times(times);
 
         self.scale /= zoom_factor.powi(zoom_times);
+        self.fit_mode = None;
     }
 }
 
@@ -332,7 +692,13 @@ impl<'a> State<'a> {
     pub fn process_action(&mut self, pa: ProcessAction<'a>) -> ProcessAction<'a> {
         match &pa {
             ProcessAction { action: a, .. } => match a {
-                Action::Noop | Action::Quit | Action::ReRender | Action::SwitchMultiNormalMode => {}
+                // Mouse gestures depend on where the mouse is, so they aren't repeated
+                Action::Noop
+                | Action::Quit
+                | Action::ReRender
+                | Action::SwitchMultiNormalMode
+                | Action::ZoomAt(..)
+                | Action::Drag(..) => {}
                 _ => {
                     self.last_action = pa.clone();
                 }
@@ -354,6 +720,10 @@ pub fn process_multi_normal_mode<'a>(
     match event {
         Event::Quit { .. } => MultiNormalAction::Quit,
 
+        Event::TextInput { text, .. } if state.bindings.contains_key(text) => {
+            (Action::Exec(state.bindings[text].clone()), times).into()
+        }
+
         Event::TextInput { text, .. } => match text.as_str() {
             // Number of times to repeat operation
             "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" => {
@@ -388,10 +758,17 @@ pub fn process_multi_normal_mode<'a>(
             "K" => (Action::Pan(PanAction::Up), times).into(),
             "L" => (Action::Pan(PanAction::Right), times).into(),
             "m" => (Action::Move, times).into(),
+            "l" => (Action::Hardlink, times).into(),
+            "p" => (Action::Symlink, times).into(),
             "o" => (Action::Zoom(ZoomAction::Out), times).into(),
             "q" => MultiNormalAction::Quit,
             "r" => (Action::Rotate(RotationDirection::Clockwise), times).into(),
             "R" => (Action::Rotate(RotationDirection::CounterClockwise), times).into(),
+            "a" => (Action::ToggleCompare, times).into(),
+            "A" => (Action::SwapCompare, times).into(),
+            "B" => (Action::ToggleFlicker, times).into(),
+            "s" => (Action::ToggleSpread, times).into(),
+            "S" => (Action::SwapSpreadDirection, times).into(),
 
             "t" => {
                 state.render_infobar = !state.render_infobar;
@@ -399,9 +776,12 @@ pub fn process_multi_normal_mode<'a>(
             }
             "v" => (Action::FlipVertical, times).into(),
             "w" => (Action::SkipForward, times).into(),
+            "x" => (Action::ToggleMark, times).into(),
             "b" => (Action::SkipBack, times).into(),
             "z" => (Action::ToggleFit, times).into(),
             "Z" => (Action::CenterImage, times).into(),
+            "F" => (Action::CycleFitMode, times).into(),
+            ":" => (Action::SwitchCommandMode, times).into(),
             _ => MultiNormalAction::Noop,
         },
 
@@ -452,9 +832,36 @@ pub fn process_normal_mode<'a>(state: &mut State<'a>, event: &Event) -> ProcessA
     use sdl2::event::WindowEvent::*;
     use sdl2::keyboard::Keycode::*;
 
+    // The letter typed after a bookmark key names the bookmark
+    if let Some(key) = state.bookmark_key.take() {
+        match event {
+            Event::TextInput { text, .. } => {
+                let mut chars = text.chars();
+                let letter = chars.next().filter(|letter| letter.is_ascii_alphabetic());
+                return match letter {
+                    Some(letter) if chars.as_str().is_empty() => match key {
+                        BookmarkKey::Set => Action::SetBookmark(letter).into(),
+                        BookmarkKey::Jump => Action::JumpToBookmark(letter).into(),
+                    },
+                    _ => Action::Noop.into(),
+                };
+            }
+            Event::KeyDown {
+                keycode: Some(Escape),
+                ..
+            } => return Action::Noop.into(),
+            // Keys are pressed before the letter they type arrives
+            _ => state.bookmark_key = Some(key),
+        }
+    }
+
     match event {
         Event::Quit { .. } => Action::Quit.into(),
 
+        Event::TextInput { text, .. } if state.bindings.contains_key(text) => {
+            Action::Exec(state.bindings[text].clone()).into()
+        }
+
         Event::TextInput { text, .. } => match text.as_str() {
             // Number of times to repeat operation
             // 0 is not captured for first digit as it does not impact counts
@@ -487,19 +894,38 @@ pub fn process_normal_mode<'a>(state: &mut State<'a>, event: &Event) -> ProcessA
             "K" => Action::Pan(PanAction::Up).into(),
             "L" => Action::Pan(PanAction::Right).into(),
             "m" => Action::Move.into(),
+            "l" => Action::Hardlink.into(),
+            "p" => Action::Symlink.into(),
             "o" => Action::Zoom(ZoomAction::Out).into(),
             "q" => Action::Quit.into(),
             "r" => Action::Rotate(RotationDirection::Clockwise).into(),
             "R" => Action::Rotate(RotationDirection::CounterClockwise).into(),
+            "a" => Action::ToggleCompare.into(),
+            "A" => Action::SwapCompare.into(),
+            "B" => Action::ToggleFlicker.into(),
+            "s" => Action::ToggleSpread.into(),
+            "S" => Action::SwapSpreadDirection.into(),
             "t" => {
                 state.render_infobar = !state.render_infobar;
                 Action::ReRender.into()
             }
             "v" => Action::FlipVertical.into(),
             "w" => Action::SkipForward.into(),
+            "x" => Action::ToggleMark.into(),
+            "X" => Action::CancelTransfer.into(),
+            "M" => {
+                state.bookmark_key = Some(BookmarkKey::Set);
+                Action::Noop.into()
+            }
+            "'" => {
+                state.bookmark_key = Some(BookmarkKey::Jump);
+                Action::Noop.into()
+            }
             "b" => Action::SkipBack.into(),
             "z" => Action::ToggleFit.into(),
             "Z" => Action::CenterImage.into(),
+            "F" => Action::CycleFitMode.into(),
+            "C" => Action::Crop.into(),
             ":" => Action::SwitchCommandMode.into(),
             _ => Action::Noop.into(),
         },
@@ -539,10 +965,43 @@ pub fn process_normal_mode<'a>(state: &mut State<'a>, event: &Event) -> ProcessA
             _ => Action::Noop.into(),
         },
 
-        Event::MouseButtonUp { mouse_btn: btn, .. } => match btn {
-            MouseButton::Left => Action::ToggleFit.into(),
-            _ => Action::Noop.into(),
-        },
+        Event::MouseButtonUp {
+            mouse_btn: MouseButton::Left,
+            clicks: 2,
+            ..
+        } => Action::ToggleFit.into(),
+
+        Event::MouseMotion {
+            mousestate,
+            x,
+            y,
+            xrel,
+            yrel,
+            ..
+        } => {
+            state.cursor = (*x, *y);
+            if mousestate.left() {
+                Action::Drag(*xrel, *yrel).into()
+            } else {
+                Action::Noop.into()
+            }
+        }
+
+        Event::MouseWheel { y, direction, .. } => {
+            // Some platforms report natural scrolling as flipped
+            let amount = match direction {
+                MouseWheelDirection::Flipped => -*y,
+                _ => *y,
+            };
+            let (x, y) = state.cursor;
+            if amount > 0 {
+                ProcessAction::new(Action::ZoomAt(ZoomAction::In, x, y), amount as usize)
+            } else if amount < 0 {
+                ProcessAction::new(Action::ZoomAt(ZoomAction::Out, x, y), -amount as usize)
+            } else {
+                Action::Noop.into()
+            }
+        }
         _ => Action::Noop.into(),
     }
 }
@@ -577,6 +1036,69 @@ pub fn process_command_mode(event: &Event) -> Action {
     }
 }
 
+/// Process SDL2 events while selecting part of the image in crop mode
+pub fn process_crop_mode(event: &Event) -> CropAction {
+    use sdl2::event::WindowEvent::*;
+    use sdl2::keyboard::Keycode::*;
+
+    match event {
+        Event::Quit { .. } => CropAction::Quit,
+        Event::TextInput { text, .. } => match text.as_str() {
+            "h" => CropAction::Move(PanAction::Left),
+            "j" => CropAction::Move(PanAction::Down),
+            "k" => CropAction::Move(PanAction::Up),
+            "l" => CropAction::Move(PanAction::Right),
+            "H" => CropAction::Resize(PanAction::Left),
+            "J" => CropAction::Resize(PanAction::Down),
+            "K" => CropAction::Resize(PanAction::Up),
+            "L" => CropAction::Resize(PanAction::Right),
+            "a" => CropAction::CycleAspect,
+            "O" => CropAction::Overwrite,
+            "q" => CropAction::Cancel,
+            _ => CropAction::Noop,
+        },
+        Event::KeyDown {
+            keycode: Some(k),
+            keymod: m,
+            ..
+        } => {
+            let shift = m.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+            let direction = match k {
+                Left => PanAction::Left,
+                Right => PanAction::Right,
+                Up => PanAction::Up,
+                Down => PanAction::Down,
+                Return | Return2 | KpEnter => return CropAction::Save,
+                Escape => return CropAction::Cancel,
+                _ => return CropAction::Noop,
+            };
+            if shift {
+                CropAction::Resize(direction)
+            } else {
+                CropAction::Move(direction)
+            }
+        }
+        Event::Window { win_event, .. } => match win_event {
+            Exposed | Resized(..) | SizeChanged(..) | Maximized => CropAction::ReRender,
+            _ => CropAction::Noop,
+        },
+        Event::MouseButtonDown {
+            mouse_btn: MouseButton::Left,
+            x,
+            y,
+            ..
+        } => CropAction::Start(*x, *y),
+        Event::MouseMotion {
+            mousestate, x, y, ..
+        } if mousestate.left() => CropAction::Drag(*x, *y),
+        Event::MouseButtonUp {
+            mouse_btn: MouseButton::Left,
+            ..
+        } => CropAction::Release,
+        _ => CropAction::Noop,
+    }
+}
+
 /// Set zoom times to 1 if times is too big for i32 value or times is 0
 fn cap_zoom_times(times: usize) -> i32 {
     let zoom_times = (times) as i32;
@@ -588,9 +1110,105 @@ fn cap_zoom_times(times: usize) -> i32 {
     }
 }
 
+/// Process SDL2 events while waiting for the user to confirm or cancel
+/// `y` or Enter confirms, `n`, `q` or Escape cancel
+pub fn process_confirm_mode(event: &Event) -> ConfirmAction {
+    use sdl2::event::WindowEvent::*;
+    use sdl2::keyboard::Keycode::*;
+
+    match event {
+        Event::Quit { .. } => ConfirmAction::Quit,
+        Event::TextInput { text, .. } => match text.as_str() {
+            "y" => ConfirmAction::Confirm,
+            "n" | "q" => ConfirmAction::Cancel,
+            _ => ConfirmAction::Noop,
+        },
+        Event::KeyDown {
+            keycode: Some(k), ..
+        } => match k {
+            Return | Return2 | KpEnter => ConfirmAction::Confirm,
+            Escape => ConfirmAction::Cancel,
+            _ => ConfirmAction::Noop,
+        },
+        Event::Window { win_event, .. } => match win_event {
+            Exposed | Resized(..) | SizeChanged(..) | Maximized => ConfirmAction::ReRender,
+            _ => ConfirmAction::Noop,
+        },
+        _ => ConfirmAction::Noop,
+    }
+}
+
+/// Process SDL2 events while asking what to do with an image whose destination is taken
+/// `o` overwrites, `s` skips, `r` renames with a suffix and `n` keeps the newer file, the same
+/// keys with shift apply to every remaining conflict, `q` or Escape cancel the remaining images
+pub fn process_conflict_mode(event: &Event) -> ConflictAction {
+    use sdl2::event::WindowEvent::*;
+    use sdl2::keyboard::Keycode::*;
+
+    match event {
+        Event::Quit { .. } => ConflictAction::Quit,
+        Event::TextInput { text, .. } => match text.as_str() {
+            "o" => ConflictAction::Choose(ConflictPolicy::Overwrite),
+            "s" => ConflictAction::Choose(ConflictPolicy::Skip),
+            "r" => ConflictAction::Choose(ConflictPolicy::Suffix),
+            "n" => ConflictAction::Choose(ConflictPolicy::KeepNewer),
+            "O" => ConflictAction::ChooseAll(ConflictPolicy::Overwrite),
+            "S" => ConflictAction::ChooseAll(ConflictPolicy::Skip),
+            "R" => ConflictAction::ChooseAll(ConflictPolicy::Suffix),
+            "N" => ConflictAction::ChooseAll(ConflictPolicy::KeepNewer),
+            "q" => ConflictAction::Cancel,
+            _ => ConflictAction::Noop,
+        },
+        Event::KeyDown {
+            keycode: Some(Escape),
+            ..
+        } => ConflictAction::Cancel,
+        Event::Window { win_event, .. } => match win_event {
+            Exposed | Resized(..) | SizeChanged(..) | Maximized => ConflictAction::ReRender,
+            _ => ConflictAction::Noop,
+        },
+        _ => ConflictAction::Noop,
+    }
+}
+
+/// Process SDL2 events while asking whether to perform a destructive action
+/// Only `y` goes ahead, any other key backs out
+pub fn process_yes_no_mode(event: &Event) -> ConfirmAction {
+    use sdl2::event::WindowEvent::*;
+    use sdl2::keyboard::Keycode::*;
+
+    match event {
+        Event::Quit { .. } => ConfirmAction::Quit,
+        Event::TextInput { text, .. } => match text.as_str() {
+            "y" | "Y" => ConfirmAction::Confirm,
+            _ => ConfirmAction::Cancel,
+        },
+        // Keys that type text are handled by their text input
+        Event::KeyDown {
+            keycode: Some(Return | Return2 | KpEnter | Escape | Delete | Backspace),
+            ..
+        } => ConfirmAction::Cancel,
+        Event::Window { win_event, .. } => match win_event {
+            Exposed | Resized(..) | SizeChanged(..) | Maximized => ConfirmAction::ReRender,
+            _ => ConfirmAction::Noop,
+        },
+        _ => ConfirmAction::Noop,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::State;
+    use super::{process_normal_mode, Action, BookmarkKey, ConfirmThresholds, FitMode, State};
+    use sdl2::event::Event;
+
+    fn text_input(text: &str) -> Event {
+        Event::TextInput {
+            timestamp: 0,
+            window_id: 0,
+            text: text.to_string(),
+        }
+    }
+
     #[test]
     fn test_zoom_in_and_then_out_gives_same_zoom_factor() {
         let mut state = State {
@@ -614,4 +1232,77 @@ mod tests {
         state.zoom_in(1);
         assert_eq!(state.scale, 1.0);
     }
+
+    #[test]
+    fn test_fit_modes_scale_to_window() {
+        let window = (800, 600);
+        // Wider than the window
+        let wide = (1600, 600);
+        assert_eq!(FitMode::Fit.scale(wide, window), 0.5);
+        assert_eq!(FitMode::Width.scale(wide, window), 0.5);
+        assert_eq!(FitMode::Height.scale(wide, window), 1.0);
+        assert_eq!(FitMode::Fill.scale(wide, window), 1.0);
+        assert_eq!(FitMode::Actual.scale(wide, window), 1.0);
+
+        // Smaller than the window is only enlarged when upscaling
+        let small = (200, 300);
+        assert_eq!(FitMode::Fit.scale(small, window), 1.0);
+        assert_eq!(FitMode::Upscale.scale(small, window), 2.0);
+        assert_eq!(FitMode::Fill.scale(small, window), 4.0);
+    }
+
+    #[test]
+    fn test_zooming_leaves_fit_mode() {
+        let mut state = State {
+            ..Default::default()
+        };
+        assert_eq!(state.fit_mode, Some(FitMode::Fit));
+        state.zoom_in(1);
+        assert_eq!(state.fit_mode, None);
+        assert_eq!("Crop".parse::<FitMode>(), Ok(FitMode::Fill));
+        assert!("stretch".parse::<FitMode>().is_err());
+    }
+
+    #[test]
+    fn test_destructive_actions_are_confirmed_from_their_threshold() {
+        let mut thresholds = ConfirmThresholds::default();
+        assert_eq!(
+            thresholds.question(&Action::Delete, 100),
+            Some("Delete 100 files? y/N".to_string())
+        );
+        assert_eq!(thresholds.question(&Action::Trash, 100), None);
+
+        thresholds.set("trash", "10").unwrap();
+        assert_eq!(thresholds.question(&Action::Trash, 9), None);
+        assert_eq!(
+            thresholds.question(&Action::Trash, 10),
+            Some("Trash 10 files? y/N".to_string())
+        );
+        thresholds.set("Delete", "0").unwrap();
+        assert_eq!(thresholds.question(&Action::Delete, 100), None);
+        assert_eq!(thresholds.question(&Action::Copy, 100), None);
+        assert!(thresholds.set("copy", "1").is_err());
+        assert!(thresholds.set("move", "all").is_err());
+    }
+
+    #[test]
+    fn test_bookmark_keys_take_the_next_letter() {
+        let mut state = State {
+            ..Default::default()
+        };
+        let action = process_normal_mode(&mut state, &text_input("M")).action;
+        assert!(matches!(action, Action::Noop));
+        assert_eq!(state.bookmark_key, Some(BookmarkKey::Set));
+        let action = process_normal_mode(&mut state, &text_input("a")).action;
+        assert!(matches!(action, Action::SetBookmark('a')));
+
+        process_normal_mode(&mut state, &text_input("'"));
+        let action = process_normal_mode(&mut state, &text_input("a")).action;
+        assert!(matches!(action, Action::JumpToBookmark('a')));
+        // Anything but a letter cancels
+        process_normal_mode(&mut state, &text_input("'"));
+        let action = process_normal_mode(&mut state, &text_input("3")).action;
+        assert!(matches!(action, Action::Noop));
+        assert_eq!(state.bookmark_key, None);
+    }
 }